@@ -1,5 +1,12 @@
-use super::{build_prompt, CommitContext, CommitMessage};
+use super::error::ProviderError;
+use super::parse::parse_commit_message;
+use super::retry::RetryPolicy;
+use super::{
+    build_conversation, ChatMessage, CommitContext, CommitMessage, CommitMessageProvider,
+    GenerationOptions, ProviderCapabilities, ResponseSchema,
+};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use colored::*;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -9,9 +16,16 @@ pub struct AnthropicClient {
     model: String,
     base_url: String,
     client: reqwest::Client,
+    retry: RetryPolicy,
 }
 
 impl AnthropicClient {
+    pub const CAPABILITIES: ProviderCapabilities = ProviderCapabilities {
+        requires_api_key: true,
+        json_mode: false,
+        multiple_choices: false,
+    };
+
     pub fn new(api_key: String, model: String, base_url: Option<String>) -> Self {
         let client = reqwest::Client::builder()
             .timeout(Duration::from_secs(30))
@@ -24,37 +38,100 @@ impl AnthropicClient {
             model,
             base_url: base_url.unwrap_or_else(|| "https://api.anthropic.com".to_string()),
             client,
+            retry: RetryPolicy::default(),
         }
     }
 
-    pub async fn generate_commit_message(
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+#[async_trait]
+impl CommitMessageProvider for AnthropicClient {
+    fn name(&self) -> &str {
+        "anthropic"
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        Self::CAPABILITIES
+    }
+
+    async fn generate_commit_message(
         &self,
         diff: &str,
         context: &CommitContext,
+        options: &GenerationOptions,
         debug: bool,
     ) -> Result<CommitMessage> {
-        let prompt = build_prompt(diff, context);
+        let conversation = build_conversation(diff, context, options);
+        let content = self
+            .generate_structured(
+                &conversation,
+                &CommitMessage::response_schema(),
+                options,
+                debug,
+            )
+            .await?;
+        parse_commit_message(&content)
+            .context("Failed to parse commit message from Anthropic response")
+    }
+
+    async fn generate_structured(
+        &self,
+        conversation: &[ChatMessage],
+        schema: &ResponseSchema,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<String> {
+        let Some(last) = conversation.len().checked_sub(1) else {
+            anyhow::bail!("Cannot send an empty conversation to Anthropic");
+        };
+        let messages = conversation
+            .iter()
+            .enumerate()
+            .map(|(i, message)| AnthropicMessage {
+                role: message.role.as_str().to_string(),
+                content: if i == last {
+                    format!(
+                        "{}\n\nRecord your answer with the {} tool.",
+                        message.content, schema.name
+                    )
+                } else {
+                    message.content.clone()
+                },
+            })
+            .collect();
 
         let request = AnthropicRequest {
             model: self.model.clone(),
-            max_tokens: 500,
-            messages: vec![AnthropicMessage {
-                role: "user".to_string(),
-                content: format!(
-                    "{}\n\nPlease respond with only the JSON object, no other text.",
-                    prompt
-                ),
+            max_tokens: schema.max_tokens,
+            temperature: options.temperature,
+            messages,
+            // Forcing the tool call makes the API return an object matching the schema
+            tools: vec![Tool {
+                name: schema.name.to_string(),
+                description: schema.description.to_string(),
+                input_schema: schema.json_schema.clone(),
             }],
+            tool_choice: ToolChoice {
+                type_field: "tool".to_string(),
+                name: schema.name.to_string(),
+            },
         };
 
-        let response = self
+        let builder = self
             .client
             .post(format!("{}/v1/messages", self.base_url))
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("content-type", "application/json")
-            .json(&request)
-            .send()
+            .json(&request);
+
+        let response = self
+            .retry
+            .send(self.name(), builder)
             .await
             .context("Failed to send request to Anthropic")?;
 
@@ -75,7 +152,7 @@ impl AnthropicClient {
                 eprintln!("Debug: Full error response: {}", error_text);
             }
 
-            anyhow::bail!("{} (Status: {})", safe_error, status);
+            return Err(ProviderError::from_status(status, safe_error).into());
         }
 
         let response_text = response
@@ -84,84 +161,47 @@ impl AnthropicClient {
             .context("Failed to read response text")?;
 
         if debug {
-            println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
-            println!("{}", response_text);
-            println!("{}", "=================================\n".cyan().bold());
+            eprintln!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
+            eprintln!("{}", response_text);
+            eprintln!("{}", "=================================\n".cyan().bold());
         }
 
         let api_response: AnthropicResponse =
             serde_json::from_str(&response_text).context("Failed to parse Anthropic response")?;
 
-        let content = api_response
+        if api_response.content.is_empty() {
+            anyhow::bail!("No response from Anthropic");
+        }
+
+        if let Some(input) = api_response
             .content
-            .first()
-            .ok_or_else(|| anyhow::anyhow!("No response from Anthropic"))?
-            .text
-            .clone();
+            .iter()
+            .find(|block| block.type_field == "tool_use")
+            .and_then(|block| block.input.clone())
+        {
+            if debug {
+                eprintln!("\n{}", "=== DEBUG: AI Tool Input ===".cyan().bold());
+                eprintln!("{}", input);
+                eprintln!("{}", "============================\n".cyan().bold());
+            }
 
-        if debug {
-            println!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
-            println!("{}", content);
-            println!("{}", "==================================\n".cyan().bold());
+            return Ok(input.to_string());
         }
 
-        // Strip markdown code block wrapper if present
-        let clean_content = if content.starts_with("```json") && content.ends_with("```") {
-            content
-                .strip_prefix("```json")
-                .and_then(|s| s.strip_suffix("```"))
-                .map(|s| s.trim())
-                .unwrap_or(&content)
-        } else if content.starts_with("```") && content.ends_with("```") {
-            content
-                .strip_prefix("```")
-                .and_then(|s| s.strip_suffix("```"))
-                .map(|s| s.trim())
-                .unwrap_or(&content)
-        } else {
-            &content
-        };
+        // Fall back to text content for compatible endpoints without tool support
+        let content: String = api_response
+            .content
+            .iter()
+            .filter_map(|block| block.text.as_deref())
+            .collect();
 
-        // Try to parse the content directly first
-        let commit_message = match serde_json::from_str::<CommitMessage>(clean_content) {
-            Ok(msg) => msg,
-            Err(_) => {
-                // If direct parsing fails, try to extract JSON object
-                // This is more robust than simple string searching
-                let mut depth = 0;
-                let mut start_idx = None;
-                let mut end_idx = None;
-
-                for (idx, ch) in clean_content.char_indices() {
-                    match ch {
-                        '{' => {
-                            if depth == 0 && start_idx.is_none() {
-                                start_idx = Some(idx);
-                            }
-                            depth += 1;
-                        }
-                        '}' => {
-                            depth -= 1;
-                            if depth == 0 && start_idx.is_some() {
-                                end_idx = Some(idx + ch.len_utf8());
-                                break;
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-
-                if let (Some(start), Some(end)) = (start_idx, end_idx) {
-                    let json_str = &clean_content[start..end];
-                    serde_json::from_str(json_str)
-                        .context("Failed to parse extracted JSON from Anthropic response")?
-                } else {
-                    anyhow::bail!("No valid JSON object found in Anthropic response");
-                }
-            }
-        };
+        if debug {
+            eprintln!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
+            eprintln!("{}", content);
+            eprintln!("{}", "==================================\n".cyan().bold());
+        }
 
-        Ok(commit_message)
+        Ok(content)
     }
 }
 
@@ -169,7 +209,11 @@ impl AnthropicClient {
 struct AnthropicRequest {
     model: String,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
     messages: Vec<AnthropicMessage>,
+    tools: Vec<Tool>,
+    tool_choice: ToolChoice,
 }
 
 #[derive(Serialize)]
@@ -183,7 +227,52 @@ struct AnthropicResponse {
     content: Vec<Content>,
 }
 
+#[derive(Serialize)]
+struct Tool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ToolChoice {
+    #[serde(rename = "type")]
+    type_field: String,
+    name: String,
+}
+
 #[derive(Deserialize)]
 struct Content {
-    text: String,
+    #[serde(rename = "type")]
+    type_field: String,
+    text: Option<String>,
+    input: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn empty_conversations_are_an_error() {
+        // Nothing listens here; the request must not be attempted at all
+        let client = AnthropicClient::new(
+            "key".to_string(),
+            "model".to_string(),
+            Some("http://127.0.0.1:9".to_string()),
+        );
+        let err = client
+            .generate_structured(
+                &[],
+                &CommitMessage::response_schema(),
+                &GenerationOptions::default(),
+                false,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot send an empty conversation to Anthropic"
+        );
+    }
 }
@@ -0,0 +1,436 @@
+//! Fits a unified diff into the prompt's size budget without losing track of
+//! which files changed.
+
+use crate::config::GENERATED_FILES;
+
+/// Smallest slice of a hunk worth sending; anything shorter is dropped.
+const MIN_PARTIAL_HUNK: usize = 160;
+
+/// Room kept for a `# ... omitted` note after a cut.
+const NOTE_LEN: usize = 40;
+
+/// Shrinks `diff` to roughly `max_chars`.
+///
+/// Every file keeps its header and a stat line, so nothing disappears
+/// silently. The rest of the budget is shared between files, smallest first,
+/// and within a file goes to the hunks with the most changed lines.
+pub fn fit_diff(diff: &str, max_chars: usize) -> String {
+    if diff.len() <= max_chars {
+        return diff.to_string();
+    }
+
+    let sections = parse_sections(diff);
+
+    // Headers and stat lines are always sent; they are the floor of the budget
+    let fixed: usize = sections
+        .iter()
+        .map(|section| {
+            section.title.as_ref().map_or(0, |t| t.len() + 1)
+                + section
+                    .files
+                    .iter()
+                    .map(|file| {
+                        file.header_len()
+                            + file.stat_line().map_or(0, |line| line.len() + 1)
+                            + NOTE_LEN
+                    })
+                    .sum::<usize>()
+        })
+        .sum();
+    let mut remaining = max_chars.saturating_sub(fixed);
+
+    // Water-filling: files needing the least are served first, and whatever
+    // they leave unused carries over to the larger ones
+    let mut order: Vec<(usize, usize)> = sections
+        .iter()
+        .enumerate()
+        .flat_map(|(s, section)| (0..section.files.len()).map(move |f| (s, f)))
+        .collect();
+    order.sort_by_key(|&(s, f)| {
+        let file = &sections[s].files[f];
+        (file.is_low_priority(), file.hunks_len())
+    });
+
+    let mut allotments = vec![Vec::new(); sections.len()];
+    for (s, section) in sections.iter().enumerate() {
+        allotments[s] = vec![Vec::new(); section.files.len()];
+    }
+
+    let mut files_left = order
+        .iter()
+        .filter(|&&(s, f)| !sections[s].files[f].is_low_priority())
+        .count();
+    for (s, f) in order {
+        let file = &sections[s].files[f];
+        // Low-priority files only get what the others left behind
+        let share = if file.is_low_priority() {
+            remaining
+        } else {
+            remaining / files_left.max(1)
+        };
+        let (selected, used) = file.select_hunks(share);
+        allotments[s][f] = selected;
+        remaining -= used;
+        files_left = files_left.saturating_sub(1);
+    }
+
+    let mut output = String::new();
+    for (s, section) in sections.iter().enumerate() {
+        if let Some(title) = &section.title {
+            output.push_str(title);
+            output.push('\n');
+        }
+        for (f, file) in section.files.iter().enumerate() {
+            file.render(&allotments[s][f], &mut output);
+        }
+    }
+    output
+}
+
+/// Splits `diff` into chunks of whole files of at most `max_chars` each,
+/// for summarising a large diff piece by piece. A file that is too large on
+/// its own gets a chunk to itself, shrunk with [`fit_diff`].
+pub fn split_diff(diff: &str, max_chars: usize) -> Vec<String> {
+    let mut files: Vec<(Option<&str>, String)> = Vec::new();
+    let mut title = None;
+    for line in diff.lines() {
+        if line.starts_with("=== ") && line.ends_with(" ===") {
+            title = Some(line);
+            continue;
+        }
+        if line.starts_with("diff --git ") {
+            files.push((title, String::new()));
+        }
+        if let Some((_, text)) = files.last_mut() {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+
+    let mut chunks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut current_title = None;
+    for (title, text) in files {
+        let text = fit_diff(&text, max_chars);
+        let heading = title
+            .filter(|&t| current.is_empty() || current_title != Some(t))
+            .map(|t| format!("{}\n", t))
+            .unwrap_or_default();
+
+        if !current.is_empty() && current.len() + heading.len() + text.len() > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if current.is_empty() {
+            if let Some(t) = title {
+                current.push_str(t);
+                current.push('\n');
+            }
+        } else {
+            current.push_str(&heading);
+        }
+        current.push_str(&text);
+        current_title = title;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// A `=== STAGED CHANGES ===` style block and the files under it.
+struct Section<'a> {
+    title: Option<&'a str>,
+    files: Vec<FileDiff<'a>>,
+}
+
+struct FileDiff<'a> {
+    path: &'a str,
+    header: Vec<&'a str>,
+    hunks: Vec<Hunk<'a>>,
+}
+
+struct Hunk<'a> {
+    lines: Vec<&'a str>,
+}
+
+/// How much of a hunk made it into the budget.
+#[derive(Clone, Copy)]
+enum Selection {
+    Full,
+    /// Only the first `n` lines, header included
+    Partial(usize),
+}
+
+fn parse_sections(diff: &str) -> Vec<Section<'_>> {
+    let mut sections = vec![Section {
+        title: None,
+        files: Vec::new(),
+    }];
+
+    for line in diff.lines() {
+        if line.starts_with("=== ") && line.ends_with(" ===") {
+            sections.push(Section {
+                title: Some(line),
+                files: Vec::new(),
+            });
+            continue;
+        }
+
+        let section = sections.last_mut().expect("sections start non-empty");
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            section.files.push(FileDiff {
+                path: paths
+                    .rsplit_once(" b/")
+                    .map_or(paths, |(_, new_path)| new_path),
+                header: vec![line],
+                hunks: Vec::new(),
+            });
+        } else if let Some(file) = section.files.last_mut() {
+            if line.starts_with("@@") {
+                file.hunks.push(Hunk { lines: vec![line] });
+            } else if let Some(hunk) = file.hunks.last_mut() {
+                hunk.lines.push(line);
+            } else if !line.starts_with("index ") {
+                // The blob ids in `index` lines mean nothing to the model
+                file.header.push(line);
+            }
+        }
+    }
+
+    sections.retain(|section| section.title.is_some() || !section.files.is_empty());
+    sections
+}
+
+impl FileDiff<'_> {
+    fn header_len(&self) -> usize {
+        self.header.iter().map(|line| line.len() + 1).sum()
+    }
+
+    fn hunks_len(&self) -> usize {
+        self.hunks.iter().map(Hunk::len).sum()
+    }
+
+    fn is_low_priority(&self) -> bool {
+        let name = self.path.rsplit('/').next().unwrap_or(self.path);
+        // Machine-written files only get what the rest of the diff leaves over
+        GENERATED_FILES
+            .iter()
+            .any(|pattern| match pattern.strip_prefix('*') {
+                Some(suffix) => name.ends_with(suffix),
+                None => name == *pattern,
+            })
+    }
+
+    /// `None` for files without hunks, e.g. binary or excluded files.
+    fn stat_line(&self) -> Option<String> {
+        if self.hunks.is_empty() {
+            return None;
+        }
+        let (added, removed) = self
+            .hunks
+            .iter()
+            .map(Hunk::changes)
+            .fold((0, 0), |(a, r), (ha, hr)| (a + ha, r + hr));
+        Some(format!(
+            "# {} | {} hunks, +{} -{}",
+            self.path,
+            self.hunks.len(),
+            added,
+            removed
+        ))
+    }
+
+    /// Picks hunks by relevance until `budget` is spent. Returns the choice
+    /// per hunk and the number of characters used.
+    fn select_hunks(&self, budget: usize) -> (Vec<Option<Selection>>, usize) {
+        let mut selected = vec![None; self.hunks.len()];
+        if self.hunks_len() <= budget {
+            return (
+                vec![Some(Selection::Full); self.hunks.len()],
+                self.hunks_len(),
+            );
+        }
+
+        let mut by_relevance: Vec<usize> = (0..self.hunks.len()).collect();
+        by_relevance.sort_by_key(|&i| {
+            let (added, removed) = self.hunks[i].changes();
+            std::cmp::Reverse(added + removed)
+        });
+
+        let mut used = 0;
+        for i in by_relevance {
+            let hunk = &self.hunks[i];
+            let left = budget - used;
+            if hunk.len() <= left {
+                selected[i] = Some(Selection::Full);
+                used += hunk.len();
+            } else if left >= MIN_PARTIAL_HUNK {
+                let (count, len) = hunk.prefix_within(left - NOTE_LEN);
+                if count > 1 {
+                    selected[i] = Some(Selection::Partial(count));
+                    used += len;
+                }
+            }
+        }
+        (selected, used)
+    }
+
+    fn render(&self, selection: &[Option<Selection>], output: &mut String) {
+        for line in &self.header {
+            output.push_str(line);
+            output.push('\n');
+        }
+        if let Some(stat_line) = self.stat_line() {
+            output.push_str(&stat_line);
+            output.push('\n');
+        }
+
+        let mut omitted = 0;
+        for (hunk, selection) in self.hunks.iter().zip(selection) {
+            let count = match selection {
+                Some(Selection::Full) => hunk.lines.len(),
+                Some(Selection::Partial(count)) => *count,
+                None => {
+                    omitted += 1;
+                    continue;
+                }
+            };
+            for line in &hunk.lines[..count] {
+                output.push_str(line);
+                output.push('\n');
+            }
+            if count < hunk.lines.len() {
+                output.push_str(&format!(
+                    "# ... {} more lines in this hunk\n",
+                    hunk.lines.len() - count
+                ));
+            }
+        }
+        if omitted > 0 {
+            output.push_str(&format!("# ... {} hunks omitted\n", omitted));
+        }
+    }
+}
+
+impl Hunk<'_> {
+    fn len(&self) -> usize {
+        self.lines.iter().map(|line| line.len() + 1).sum()
+    }
+
+    fn changes(&self) -> (usize, usize) {
+        let added = self.lines.iter().filter(|l| l.starts_with('+')).count();
+        let removed = self.lines.iter().filter(|l| l.starts_with('-')).count();
+        (added, removed)
+    }
+
+    /// The longest run of leading lines that fits in `budget`, as
+    /// (line count, characters).
+    fn prefix_within(&self, budget: usize) -> (usize, usize) {
+        let mut len = 0;
+        let mut count = 0;
+        for line in &self.lines {
+            if len + line.len() + 1 > budget {
+                break;
+            }
+            len += line.len() + 1;
+            count += 1;
+        }
+        (count, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_diff(path: &str, hunks: usize, lines_per_hunk: usize) -> String {
+        let mut diff = format!(
+            "diff --git a/{0} b/{0}\nindex 1111111..2222222 100644\n--- a/{0}\n+++ b/{0}\n",
+            path
+        );
+        for h in 0..hunks {
+            diff.push_str(&format!("@@ -{0},3 +{0},3 @@\n", h * 100 + 1));
+            for l in 0..lines_per_hunk {
+                diff.push_str(&format!("+{} line {} of hunk {}\n", path, l, h));
+            }
+        }
+        diff
+    }
+
+    #[test]
+    fn small_diffs_are_untouched() {
+        let diff = file_diff("src/main.rs", 1, 3);
+        assert_eq!(fit_diff(&diff, 10_000), diff);
+    }
+
+    #[test]
+    fn every_file_keeps_header_and_stat() {
+        let mut diff = String::from("=== STAGED CHANGES ===\n\n");
+        for i in 0..20 {
+            diff.push_str(&file_diff(&format!("src/file_{}.rs", i), 3, 20));
+        }
+
+        let fitted = fit_diff(&diff, 3000);
+        assert!(fitted.starts_with("=== STAGED CHANGES ===\n"));
+        for i in 0..20 {
+            let path = format!("src/file_{}.rs", i);
+            assert!(fitted.contains(&format!("diff --git a/{0} b/{0}", path)));
+            assert!(fitted.contains(&format!("# {} | 3 hunks, +60 -0", path)));
+        }
+        assert!(!fitted.contains("index 1111111"));
+    }
+
+    #[test]
+    fn budget_is_shared_between_files() {
+        let diff = format!(
+            "{}{}",
+            file_diff("src/big.rs", 10, 40),
+            file_diff("src/small.rs", 1, 5)
+        );
+
+        let fitted = fit_diff(&diff, 2500);
+        assert!(fitted.len() <= 2500);
+        // The small file fits entirely; the big one gets the rest
+        assert!(fitted.contains("+src/small.rs line 4 of hunk 0"));
+        assert!(fitted.contains("+src/big.rs line 0 of hunk"));
+        assert!(fitted.contains("hunks omitted"));
+    }
+
+    #[test]
+    fn splits_on_file_boundaries() {
+        let mut diff = String::from("=== STAGED CHANGES ===\n\n");
+        for i in 0..6 {
+            diff.push_str(&file_diff(&format!("src/file_{}.rs", i), 1, 10));
+        }
+        diff.push_str("\n\n=== UNSTAGED CHANGES ===\n\n");
+        diff.push_str(&file_diff("src/huge.rs", 20, 40));
+
+        let chunks = split_diff(&diff, 1500);
+        assert!(chunks.len() > 2);
+        for chunk in &chunks {
+            assert!(chunk.len() <= 1500, "chunk of {} chars", chunk.len());
+            assert!(chunk.starts_with("=== "));
+        }
+        for i in 0..6 {
+            let header = format!("diff --git a/src/file_{0}.rs b/src/file_{0}.rs", i);
+            assert_eq!(chunks.iter().filter(|c| c.contains(&header)).count(), 1);
+        }
+        assert!(chunks
+            .last()
+            .unwrap()
+            .starts_with("=== UNSTAGED CHANGES ===\ndiff --git a/src/huge.rs"));
+    }
+
+    #[test]
+    fn lock_files_yield_to_source_files() {
+        let diff = format!(
+            "{}{}",
+            file_diff("Cargo.lock", 2, 40),
+            file_diff("src/lib.rs", 2, 20)
+        );
+
+        let fitted = fit_diff(&diff, 2000);
+        assert!(fitted.contains("+src/lib.rs line 19 of hunk 1"));
+        assert!(fitted.contains("# Cargo.lock | 2 hunks, +80 -0"));
+    }
+}
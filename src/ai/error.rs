@@ -0,0 +1,83 @@
+use reqwest::StatusCode;
+use std::fmt;
+
+/// Broad category of a provider failure, used to decide whether another
+/// provider in the fallback chain is worth trying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Rejected credentials or permissions (401/403)
+    Auth,
+    /// Still rate limited after retries (429)
+    RateLimit,
+    /// The request or connection timed out
+    Timeout,
+    /// Server errors (5xx) or the endpoint could not be reached
+    Unavailable,
+    /// The model's output could not be turned into a commit message
+    Parse,
+    Other,
+}
+
+impl ErrorKind {
+    /// Failures that are specific to one vendor or model, where the next
+    /// provider has a fair chance of succeeding.
+    pub fn should_fall_back(self) -> bool {
+        !matches!(self, ErrorKind::Other)
+    }
+}
+
+#[derive(Debug)]
+pub struct ProviderError {
+    pub kind: ErrorKind,
+    message: String,
+}
+
+impl ProviderError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// Error for a non-success HTTP status; `message` is the sanitized text shown to the user.
+    pub fn from_status(status: StatusCode, message: &str) -> Self {
+        let kind = match status.as_u16() {
+            401 | 403 => ErrorKind::Auth,
+            408 => ErrorKind::Timeout,
+            429 => ErrorKind::RateLimit,
+            500..=599 => ErrorKind::Unavailable,
+            _ => ErrorKind::Other,
+        };
+        Self::new(kind, format!("{} (Status: {})", message, status))
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+/// Classifies any error returned by a provider by walking its cause chain.
+pub fn error_kind(err: &anyhow::Error) -> ErrorKind {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<ProviderError>() {
+            return e.kind;
+        }
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            if e.is_timeout() {
+                return ErrorKind::Timeout;
+            }
+            if e.is_connect() {
+                return ErrorKind::Unavailable;
+            }
+        }
+        if cause.is::<serde_json::Error>() {
+            return ErrorKind::Parse;
+        }
+    }
+    ErrorKind::Other
+}
@@ -0,0 +1,244 @@
+use super::error::error_kind;
+use super::{
+    AIClient, ChatMessage, CommitContext, CommitMessage, CommitMessageProvider, GenerationOptions,
+    ProviderCapabilities, ResponseSchema,
+};
+use crate::ui::CommitUI;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::future::Future;
+
+/// Tries each provider in order, moving on when one fails for a reason the
+/// next provider might not share (auth, rate limit, timeout, outage, parse).
+pub struct FallbackClient {
+    name: String,
+    providers: Vec<AIClient>,
+}
+
+impl FallbackClient {
+    pub fn new(providers: Vec<AIClient>) -> Self {
+        let name = providers
+            .iter()
+            .map(|provider| provider.name())
+            .collect::<Vec<_>>()
+            .join(" → ");
+        Self { name, providers }
+    }
+}
+
+#[async_trait]
+impl CommitMessageProvider for FallbackClient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Only what every provider in the chain supports.
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            requires_api_key: self
+                .providers
+                .iter()
+                .any(|provider| provider.capabilities().requires_api_key),
+            json_mode: self
+                .providers
+                .iter()
+                .all(|provider| provider.capabilities().json_mode),
+            multiple_choices: self
+                .providers
+                .iter()
+                .all(|provider| provider.capabilities().multiple_choices),
+        }
+    }
+
+    async fn generate_commit_message(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<CommitMessage> {
+        let mut messages = self
+            .generate_candidates(diff, context, options, 1, debug)
+            .await?;
+        Ok(messages.remove(0))
+    }
+
+    async fn generate_candidates(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        options: &GenerationOptions,
+        count: u32,
+        debug: bool,
+    ) -> Result<Vec<CommitMessage>> {
+        let (mut messages, provider) = self
+            .first_success(|provider| {
+                provider.generate_candidates(diff, context, options, count, debug)
+            })
+            .await?;
+        for message in &mut messages {
+            message.provider = Some(provider.name().to_string());
+        }
+        Ok(messages)
+    }
+
+    async fn generate_structured(
+        &self,
+        conversation: &[ChatMessage],
+        schema: &ResponseSchema,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<String> {
+        let (reply, _) = self
+            .first_success(|provider| {
+                provider.generate_structured(conversation, schema, options, debug)
+            })
+            .await?;
+        Ok(reply)
+    }
+}
+
+impl FallbackClient {
+    /// Runs `request` against each provider in turn, returning the first
+    /// success along with the provider that produced it.
+    async fn first_success<'a, T, F, Fut>(&'a self, request: F) -> Result<(T, &'a AIClient)>
+    where
+        F: Fn(&'a AIClient) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut providers = self.providers.iter().peekable();
+
+        while let Some(provider) = providers.next() {
+            match request(provider).await {
+                Ok(value) => return Ok((value, provider)),
+                Err(e) => {
+                    let kind = error_kind(&e);
+                    match providers.peek() {
+                        Some(next) if kind.should_fall_back() => {
+                            CommitUI::show_warning(&format!(
+                                "{} failed ({:?}): {}. Falling back to {}...",
+                                provider.name(),
+                                kind,
+                                e,
+                                next.name()
+                            ));
+                        }
+                        _ => return Err(e),
+                    }
+                }
+            }
+        }
+
+        anyhow::bail!("No AI providers configured")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::error::{ErrorKind, ProviderError};
+    use crate::ai::stub::{message_json, StubProvider};
+    use std::sync::{Arc, Mutex};
+
+    type Calls = Arc<Mutex<Vec<String>>>;
+
+    /// A provider that logs its name to `calls` and fails with `error`, or
+    /// answers with a message naming itself.
+    fn provider(name: &str, error: Option<ErrorKind>, calls: &Calls) -> AIClient {
+        let (label, calls) = (name.to_string(), calls.clone());
+        Box::new(StubProvider::new(name, move |_| {
+            calls.lock().unwrap().push(label.clone());
+            match error {
+                Some(kind) => Err(ProviderError::new(kind, format!("{} failed", label)).into()),
+                None => Ok(message_json(&label)),
+            }
+        }))
+    }
+
+    fn context() -> CommitContext {
+        CommitContext {
+            branch_name: None,
+            file_count: 1,
+            added_lines: 1,
+            removed_lines: 0,
+            previous_message: None,
+        }
+    }
+
+    async fn generate(client: &FallbackClient) -> Result<Vec<CommitMessage>> {
+        client
+            .generate_candidates("diff", &context(), &GenerationOptions::default(), 1, false)
+            .await
+    }
+
+    #[tokio::test]
+    async fn tries_providers_in_order_and_attributes_the_answer() {
+        let calls = Calls::default();
+        let client = FallbackClient::new(vec![
+            provider("first", Some(ErrorKind::Auth), &calls),
+            provider("second", Some(ErrorKind::Unavailable), &calls),
+            provider("third", None, &calls),
+            provider("fourth", None, &calls),
+        ]);
+
+        let messages = generate(&client).await.unwrap();
+        assert_eq!(*calls.lock().unwrap(), ["first", "second", "third"]);
+        assert_eq!(messages[0].description_en, "third");
+        assert_eq!(messages[0].provider.as_deref(), Some("third"));
+        assert_eq!(client.name(), "first → second → third → fourth");
+    }
+
+    #[tokio::test]
+    async fn only_provider_specific_failures_fall_back() {
+        for kind in [
+            ErrorKind::Auth,
+            ErrorKind::RateLimit,
+            ErrorKind::Timeout,
+            ErrorKind::Unavailable,
+            ErrorKind::Parse,
+        ] {
+            let calls = Calls::default();
+            let client = FallbackClient::new(vec![
+                provider("first", Some(kind), &calls),
+                provider("second", None, &calls),
+            ]);
+            let messages = generate(&client).await.unwrap();
+            assert_eq!(
+                messages[0].provider.as_deref(),
+                Some("second"),
+                "{:?}",
+                kind
+            );
+        }
+
+        let calls = Calls::default();
+        let client = FallbackClient::new(vec![
+            provider("first", Some(ErrorKind::Other), &calls),
+            provider("second", None, &calls),
+        ]);
+        let err = generate(&client).await.unwrap_err();
+        assert_eq!(err.to_string(), "first failed");
+        assert_eq!(*calls.lock().unwrap(), ["first"]);
+    }
+
+    #[tokio::test]
+    async fn the_last_error_is_returned_when_every_provider_fails() {
+        let calls = Calls::default();
+        let client = FallbackClient::new(vec![
+            provider("first", Some(ErrorKind::Timeout), &calls),
+            provider("second", Some(ErrorKind::RateLimit), &calls),
+        ]);
+
+        let err = client
+            .generate_structured(
+                &[],
+                &CommitMessage::response_schema(),
+                &GenerationOptions::default(),
+                false,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "second failed");
+        assert_eq!(*calls.lock().unwrap(), ["first", "second"]);
+    }
+}
@@ -0,0 +1,397 @@
+use super::error::{ErrorKind, ProviderError};
+use super::parse::parse_commit_message;
+use super::retry::RetryPolicy;
+use super::{
+    build_conversation, ChatMessage, CommitContext, CommitMessage, CommitMessageProvider,
+    GenerationOptions, ProviderCapabilities, ResponseSchema, Role, DEFAULT_TEMPERATURE,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+pub struct GeminiClient {
+    api_key: String,
+    model: String,
+    base_url: String,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+impl GeminiClient {
+    pub const CAPABILITIES: ProviderCapabilities = ProviderCapabilities {
+        requires_api_key: true,
+        json_mode: true,
+        multiple_choices: false,
+    };
+
+    pub fn new(api_key: String, model: String, base_url: Option<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self {
+            api_key,
+            model,
+            base_url: base_url
+                .unwrap_or_else(|| "https://generativelanguage.googleapis.com/v1beta".to_string()),
+            client,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+#[async_trait]
+impl CommitMessageProvider for GeminiClient {
+    fn name(&self) -> &str {
+        "gemini"
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        Self::CAPABILITIES
+    }
+
+    async fn generate_commit_message(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<CommitMessage> {
+        let conversation = build_conversation(diff, context, options);
+        let content = self
+            .generate_structured(
+                &conversation,
+                &CommitMessage::response_schema(),
+                options,
+                debug,
+            )
+            .await?;
+        // responseSchema should yield a bare object; parse tolerantly in case a proxy drops it
+        parse_commit_message(&content)
+            .context("Failed to parse commit message from Gemini response")
+    }
+
+    async fn generate_structured(
+        &self,
+        conversation: &[ChatMessage],
+        schema: &ResponseSchema,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<String> {
+        let request = GeminiRequest {
+            system_instruction: GeminiContent {
+                role: None,
+                parts: vec![Part {
+                    text: "You are a helpful assistant that generates git commit messages in JSON format.".to_string(),
+                }],
+            },
+            contents: conversation
+                .iter()
+                .map(|message| GeminiContent {
+                    role: Some(
+                        match message.role {
+                            Role::User => "user",
+                            Role::Assistant => "model",
+                        }
+                        .to_string(),
+                    ),
+                    parts: vec![Part {
+                        text: message.content.clone(),
+                    }],
+                })
+                .collect(),
+            generation_config: GenerationConfig {
+                temperature: options.temperature.unwrap_or(DEFAULT_TEMPERATURE),
+                // Thinking models count their reasoning against this limit, so
+                // small budgets can run out before any JSON is written
+                max_output_tokens: schema.max_tokens.max(1000),
+                response_mime_type: "application/json".to_string(),
+                response_schema: to_gemini_schema(&schema.json_schema),
+            },
+        };
+
+        let builder = self
+            .client
+            .post(format!(
+                "{}/models/{}:generateContent",
+                self.base_url, self.model
+            ))
+            .header("x-goog-api-key", &self.api_key)
+            .json(&request);
+
+        let response = self
+            .retry
+            .send(self.name(), builder)
+            .await
+            .context("Failed to send request to Gemini")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await?;
+
+            // Sanitize error message to avoid exposing sensitive details
+            let safe_error = match status.as_u16() {
+                400 => "Invalid request. Please check the model name and your configuration.",
+                401 | 403 => "Authentication failed. Please check your API key.",
+                429 => "Rate limit exceeded. Please try again later.",
+                500..=599 => "Gemini service error. Please try again later.",
+                _ => "Request failed. Please check your configuration.",
+            };
+
+            if debug {
+                eprintln!("Debug: Full error response: {}", error_text);
+            }
+
+            return Err(ProviderError::from_status(status, safe_error).into());
+        }
+
+        let response_text = response
+            .text()
+            .await
+            .context("Failed to read response text")?;
+
+        if debug {
+            eprintln!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
+            eprintln!("{}", response_text);
+            eprintln!("{}", "=================================\n".cyan().bold());
+        }
+
+        let api_response: GeminiResponse =
+            serde_json::from_str(&response_text).context("Failed to parse Gemini response")?;
+
+        if let Some(reason) = api_response
+            .prompt_feedback
+            .and_then(|feedback| feedback.block_reason)
+        {
+            if debug {
+                eprintln!("Debug: Prompt blocked with blockReason={}", reason);
+            }
+            anyhow::bail!("The response was blocked by the provider's content filter.");
+        }
+
+        let candidate = api_response
+            .candidates
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("No response from Gemini"))?;
+
+        check_finish_reason(candidate.finish_reason.as_deref())?;
+
+        let content: String = candidate
+            .content
+            .map(|content| content.parts.into_iter().map(|part| part.text).collect())
+            .ok_or_else(|| anyhow::anyhow!("Response content is null"))?;
+
+        if debug {
+            eprintln!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
+            eprintln!("{}", content);
+            eprintln!("{}", "==================================\n".cyan().bold());
+        }
+
+        Ok(content)
+    }
+}
+
+/// Turns a stop other than a normal one into the error OpenAI's matching
+/// `finish_reason` gives: truncation is a parse failure, a block is final.
+fn check_finish_reason(reason: Option<&str>) -> Result<()> {
+    match reason {
+        Some("MAX_TOKENS") => Err(ProviderError::new(ErrorKind::Parse, "AI response was truncated before completing the JSON (finishReason=MAX_TOKENS). Try reducing the diff size or switching models.").into()),
+        Some("SAFETY")
+        | Some("RECITATION")
+        | Some("BLOCKLIST")
+        | Some("PROHIBITED_CONTENT")
+        | Some("SPII") => {
+            anyhow::bail!("The response was blocked by the provider's content filter.");
+        }
+        Some("STOP") | Some("FINISH_REASON_UNSPECIFIED") | None => Ok(()),
+        Some(other) => {
+            anyhow::bail!("Unexpected finishReason '{}' from AI response.", other);
+        }
+    }
+}
+
+/// Converts a strict-mode JSON Schema to the OpenAPI subset Gemini's
+/// `responseSchema` accepts: upper-case type names, `["string", "null"]` as
+/// `nullable`, no `additionalProperties`, and `propertyOrdering` taken from
+/// `required`, which lists every property in strict mode.
+fn to_gemini_schema(schema: &Value) -> Value {
+    let Value::Object(fields) = schema else {
+        return schema.clone();
+    };
+    let is_nullable = |property: &Value| {
+        property["type"]
+            .as_array()
+            .is_some_and(|types| types.iter().any(|t| t == "null"))
+    };
+
+    let mut converted = serde_json::Map::new();
+    for (key, value) in fields {
+        match key.as_str() {
+            "type" => {
+                let name = match value {
+                    Value::Array(types) => types.iter().find(|t| *t != "null"),
+                    other => Some(other),
+                };
+                if let Some(name) = name.and_then(Value::as_str) {
+                    converted.insert(key.clone(), Value::from(name.to_uppercase()));
+                }
+                if is_nullable(schema) {
+                    converted.insert("nullable".to_string(), Value::Bool(true));
+                }
+            }
+            "properties" | "items" => {
+                let value = match value {
+                    Value::Object(properties) if key == "properties" => Value::Object(
+                        properties
+                            .iter()
+                            .map(|(name, property)| (name.clone(), to_gemini_schema(property)))
+                            .collect(),
+                    ),
+                    other => to_gemini_schema(other),
+                };
+                converted.insert(key.clone(), value);
+            }
+            "required" => {
+                // Gemini wants nullable fields left out of `required`
+                let required: Vec<Value> = value
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter(|name| {
+                        let property = name.as_str().map(|name| &schema["properties"][name]);
+                        !property.is_some_and(is_nullable)
+                    })
+                    .cloned()
+                    .collect();
+                converted.insert(key.clone(), Value::Array(required));
+                converted.insert("propertyOrdering".to_string(), value.clone());
+            }
+            "additionalProperties" => {}
+            _ => {
+                converted.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Value::Object(converted)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    system_instruction: GeminiContent,
+    contents: Vec<GeminiContent>,
+    generation_config: GenerationConfig,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GeminiContent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Part {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    temperature: f32,
+    max_output_tokens: u32,
+    response_mime_type: String,
+    response_schema: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    prompt_feedback: Option<PromptFeedback>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<GeminiContent>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::error::error_kind;
+
+    #[test]
+    fn commit_schema_converts_to_gemini_form() {
+        let string_array = serde_json::json!({ "type": "ARRAY", "items": { "type": "STRING" } });
+        let expected = serde_json::json!({
+            "type": "OBJECT",
+            "properties": {
+                "type": {
+                    "type": "STRING",
+                    "enum": ["feat", "fix", "docs", "style", "refactor", "test", "chore", "perf"],
+                    "description": "Conventional Commits type"
+                },
+                "scope": {
+                    "type": "STRING",
+                    "nullable": true,
+                    "description": "Component or area affected"
+                },
+                "description": { "type": "STRING", "description": "Short summary in Chinese" },
+                "description_en": { "type": "STRING", "description": "Short summary in English" },
+                "body": string_array,
+                "body_en": string_array,
+                "breaking_change": {
+                    "type": "STRING",
+                    "nullable": true,
+                    "description": "Description of the breaking change, if any"
+                }
+            },
+            "required": ["type", "description", "description_en", "body", "body_en"],
+            "propertyOrdering": [
+                "type", "scope", "description", "description_en", "body", "body_en", "breaking_change"
+            ]
+        });
+
+        assert_eq!(to_gemini_schema(&CommitMessage::json_schema()), expected);
+    }
+
+    #[test]
+    fn finish_reasons_map_to_the_openai_error_kinds() {
+        // OpenAI's `length` ends in a Parse error, `content_filter` in Other
+        let truncated = check_finish_reason(Some("MAX_TOKENS")).unwrap_err();
+        assert_eq!(error_kind(&truncated), ErrorKind::Parse);
+        assert!(truncated
+            .to_string()
+            .contains("truncated before completing the JSON (finishReason=MAX_TOKENS)"));
+
+        let blocked = check_finish_reason(Some("SAFETY")).unwrap_err();
+        assert_eq!(error_kind(&blocked), ErrorKind::Other);
+        assert!(blocked.to_string().contains("content filter"));
+
+        assert!(check_finish_reason(Some("STOP")).is_ok());
+        assert!(check_finish_reason(None).is_ok());
+    }
+}
@@ -1,8 +1,23 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
 use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
 
 pub mod anthropic;
+pub mod budget;
+pub mod error;
+pub mod fallback;
+pub mod gemini;
+pub mod ollama;
 pub mod openai;
+pub mod parse;
+pub mod pr;
+pub mod redact;
+pub mod retry;
+pub mod split;
+#[cfg(test)]
+pub mod stub;
+pub mod summarize;
 
 #[derive(Debug, Clone)]
 pub struct CommitContext {
@@ -10,11 +25,57 @@ pub struct CommitContext {
     pub file_count: usize,
     pub added_lines: usize,
     pub removed_lines: usize,
+    /// Message of the commit being amended, if any
+    pub previous_message: Option<String>,
+}
+
+/// Sampling temperature used when a request doesn't override it.
+pub const DEFAULT_TEMPERATURE: f32 = 0.7;
+
+/// Per-request settings that may change between attempts in one session.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationOptions {
+    /// Overrides the provider's default sampling temperature
+    pub temperature: Option<f32>,
+    /// Earlier answers and the user's instructions for revising them, oldest first
+    pub feedback: Vec<Feedback>,
+    /// Summaries of the parts of a diff too large to send whole; when set,
+    /// the diff passed alongside is only an overview of the changed files
+    pub summaries: Vec<String>,
+}
+
+/// A previous message the user asked to revise, e.g. "make it shorter".
+#[derive(Debug, Clone)]
+pub struct Feedback {
+    pub previous: CommitMessage,
+    pub instruction: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+/// One turn of the conversation sent to a provider.
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CommitMessage {
-    #[serde(alias = "type", alias = "commit_type")]
+    #[serde(rename = "type", alias = "commit_type")]
     pub commit_type: String,
     pub scope: Option<String>,
     pub description: String,
@@ -26,6 +87,9 @@ pub struct CommitMessage {
     pub body_en: Option<Vec<String>>, // 英文说明
     #[serde(deserialize_with = "deserialize_breaking_change")]
     pub breaking_change: Option<String>,
+    /// Provider that produced this message when a fallback chain is used
+    #[serde(skip)]
+    pub provider: Option<String>,
 }
 
 fn deserialize_body<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
@@ -69,6 +133,59 @@ where
 }
 
 impl CommitMessage {
+    /// Name used for the schema in structured-output and tool-call requests.
+    pub const SCHEMA_NAME: &'static str = "commit_message";
+
+    /// JSON Schema describing the object the prompt asks for.
+    ///
+    /// Written to satisfy OpenAI strict mode: every property is required and
+    /// optional fields are nullable instead of omitted.
+    pub fn json_schema() -> serde_json::Value {
+        let string_array = serde_json::json!({ "type": "array", "items": { "type": "string" } });
+
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "type": {
+                    "type": "string",
+                    "enum": ["feat", "fix", "docs", "style", "refactor", "test", "chore", "perf"],
+                    "description": "Conventional Commits type"
+                },
+                "scope": {
+                    "type": ["string", "null"],
+                    "description": "Component or area affected"
+                },
+                "description": {
+                    "type": "string",
+                    "description": "Short summary in Chinese"
+                },
+                "description_en": {
+                    "type": "string",
+                    "description": "Short summary in English"
+                },
+                "body": string_array,
+                "body_en": string_array,
+                "breaking_change": {
+                    "type": ["string", "null"],
+                    "description": "Description of the breaking change, if any"
+                }
+            },
+            "required": [
+                "type", "scope", "description", "description_en", "body", "body_en", "breaking_change"
+            ],
+            "additionalProperties": false
+        })
+    }
+
+    pub fn response_schema() -> ResponseSchema {
+        ResponseSchema {
+            name: Self::SCHEMA_NAME,
+            description: "Record the generated bilingual commit message.",
+            json_schema: Self::json_schema(),
+            max_tokens: 500,
+        }
+    }
+
     pub fn format_conventional(&self) -> String {
         let mut message = String::new();
 
@@ -121,46 +238,336 @@ impl CommitMessage {
 
         message
     }
+
+    /// First line of the conventional message: `type(scope): description`.
+    pub fn header(&self) -> String {
+        match &self.scope {
+            Some(scope) => format!("{}({}): {}", self.commit_type, scope, self.description),
+            None => format!("{}: {}", self.commit_type, self.description),
+        }
+    }
+
+    /// Combines several candidates into one: the header of the first, and the
+    /// body lines of all of them with duplicates dropped.
+    pub fn merge(candidates: &[CommitMessage]) -> Option<CommitMessage> {
+        let mut merged = candidates.first()?.clone();
+        let mut body = Vec::new();
+        let mut body_en = Vec::new();
+
+        for candidate in candidates {
+            let zh_lines = candidate.body.as_deref().unwrap_or_default();
+            let en_lines = candidate.body_en.as_deref().unwrap_or_default();
+
+            for i in 0..zh_lines.len().max(en_lines.len()) {
+                let zh = zh_lines.get(i).cloned().unwrap_or_default();
+                let en = en_lines.get(i).cloned().unwrap_or_default();
+                let seen = (!zh.is_empty() && body.contains(&zh))
+                    || (!en.is_empty() && body_en.contains(&en));
+                if !seen {
+                    body.push(zh);
+                    body_en.push(en);
+                }
+            }
+
+            if merged.breaking_change.is_none() {
+                merged.breaking_change = candidate.breaking_change.clone();
+            }
+        }
+
+        merged.body = Some(body);
+        merged.body_en = Some(body_en);
+        Some(merged)
+    }
+}
+
+/// Shape of a structured reply, in each form the providers accept.
+#[derive(Debug, Clone)]
+pub struct ResponseSchema {
+    /// Name of the Anthropic tool and the OpenAI `json_schema`
+    pub name: &'static str,
+    /// Description of the Anthropic tool
+    pub description: &'static str,
+    /// JSON Schema in OpenAI strict-mode form, also used for Anthropic and
+    /// converted for Gemini
+    pub json_schema: serde_json::Value,
+    /// Output tokens to allow for one reply
+    pub max_tokens: u32,
 }
 
-pub enum AIClient {
-    OpenAI(openai::OpenAIClient),
-    Anthropic(anthropic::AnthropicClient),
+/// What a provider supports, so callers can adapt without knowing the backend.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProviderCapabilities {
+    /// Whether the provider needs an API key to authenticate.
+    pub requires_api_key: bool,
+    /// Whether the provider can be forced to reply with a JSON object.
+    pub json_mode: bool,
+    /// Whether one request can return several alternative messages.
+    pub multiple_choices: bool,
 }
 
-impl AIClient {
-    pub async fn generate_commit_message(
+/// A backend that turns a diff into a structured commit message.
+#[async_trait]
+pub trait CommitMessageProvider: Send + Sync {
+    /// Short identifier of the provider, e.g. "openai".
+    fn name(&self) -> &str;
+
+    fn capabilities(&self) -> ProviderCapabilities;
+
+    /// Sends `conversation` and returns the reply as JSON text, constrained
+    /// to `schema` where the provider supports it. For requests whose answer
+    /// isn't a single commit message; parse it with [`parse::parse_object`].
+    async fn generate_structured(
+        &self,
+        conversation: &[ChatMessage],
+        schema: &ResponseSchema,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<String>;
+
+    async fn generate_commit_message(
         &self,
         diff: &str,
         context: &CommitContext,
+        options: &GenerationOptions,
         debug: bool,
-    ) -> Result<CommitMessage> {
-        match self {
-            AIClient::OpenAI(client) => client.generate_commit_message(diff, context, debug).await,
-            AIClient::Anthropic(client) => {
-                client.generate_commit_message(diff, context, debug).await
+    ) -> Result<CommitMessage>;
+
+    /// Generates `count` alternative messages. Providers without native
+    /// support get one concurrent request per candidate; failed requests
+    /// are dropped as long as at least one succeeds.
+    async fn generate_candidates(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        options: &GenerationOptions,
+        count: u32,
+        debug: bool,
+    ) -> Result<Vec<CommitMessage>> {
+        let requests =
+            (0..count.max(1)).map(|_| self.generate_commit_message(diff, context, options, debug));
+        let mut candidates = Vec::new();
+        let mut first_err = None;
+        for result in futures::future::join_all(requests).await {
+            match result {
+                Ok(message) => candidates.push(message),
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_err {
+            Some(e) if candidates.is_empty() => Err(e),
+            _ => Ok(candidates),
+        }
+    }
+}
+
+pub type AIClient = Box<dyn CommitMessageProvider>;
+
+/// Settings handed to a provider factory when a client is created.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderConfig {
+    pub api_key: Option<String>,
+    pub model: String,
+    pub base_url: Option<String>,
+    /// Azure OpenAI deployment name
+    pub deployment: Option<String>,
+    /// Azure OpenAI `api-version` query parameter
+    pub api_version: Option<String>,
+    /// "bearer" or "api-key"; providers pick their own default when unset
+    pub auth_header: Option<String>,
+    pub retry: retry::RetryPolicy,
+}
+
+impl ProviderConfig {
+    /// Returns the API key, failing for providers that cannot work without one.
+    pub fn require_api_key(&self) -> Result<String> {
+        self.api_key.clone().context("No API key provided")
+    }
+}
+
+pub type ProviderFactory = Box<dyn Fn(ProviderConfig) -> Result<AIClient> + Send + Sync>;
+
+struct ProviderEntry {
+    capabilities: ProviderCapabilities,
+    factory: ProviderFactory,
+}
+
+/// Maps provider names to factories. Names are matched case-insensitively.
+pub struct ProviderRegistry {
+    providers: HashMap<String, ProviderEntry>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self {
+            providers: HashMap::new(),
+        }
+    }
+
+    /// Registry with every provider shipped in this crate.
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register("openai", openai::OpenAIClient::CAPABILITIES, |config| {
+            let mut client = openai::OpenAIClient::new(
+                config.require_api_key()?,
+                config.model.clone(),
+                config.base_url.clone(),
+            )
+            .with_retry(config.retry);
+            if let Some(auth_header) = &config.auth_header {
+                client = client.with_auth_header(openai::AuthHeader::parse(auth_header)?);
+            }
+            Ok(Box::new(client))
+        });
+        registry.register("azure", openai::OpenAIClient::CAPABILITIES, |config| {
+            let endpoint = config.base_url.as_deref().context(
+                "Azure OpenAI requires base_url, e.g. https://<resource>.openai.azure.com",
+            )?;
+            let mut client = openai::OpenAIClient::azure(
+                config.require_api_key()?,
+                config.model.clone(),
+                endpoint,
+                config.deployment.clone(),
+                config.api_version.clone(),
+            )
+            .with_retry(config.retry);
+            if let Some(auth_header) = &config.auth_header {
+                client = client.with_auth_header(openai::AuthHeader::parse(auth_header)?);
             }
+            Ok(Box::new(client))
+        });
+        registry.register(
+            "anthropic",
+            anthropic::AnthropicClient::CAPABILITIES,
+            |config| {
+                Ok(Box::new(
+                    anthropic::AnthropicClient::new(
+                        config.require_api_key()?,
+                        config.model,
+                        config.base_url,
+                    )
+                    .with_retry(config.retry),
+                ))
+            },
+        );
+        registry.register("gemini", gemini::GeminiClient::CAPABILITIES, |config| {
+            Ok(Box::new(
+                gemini::GeminiClient::new(config.require_api_key()?, config.model, config.base_url)
+                    .with_retry(config.retry),
+            ))
+        });
+        registry.register("ollama", ollama::OllamaClient::CAPABILITIES, |config| {
+            Ok(Box::new(
+                ollama::OllamaClient::new(config.api_key, config.model, config.base_url)
+                    .with_retry(config.retry),
+            ))
+        });
+        registry
+    }
+
+    /// Registers a factory, replacing any existing one with the same name.
+    pub fn register<F>(&mut self, name: &str, capabilities: ProviderCapabilities, factory: F)
+    where
+        F: Fn(ProviderConfig) -> Result<AIClient> + Send + Sync + 'static,
+    {
+        self.providers.insert(
+            name.to_lowercase(),
+            ProviderEntry {
+                capabilities,
+                factory: Box::new(factory),
+            },
+        );
+    }
+
+    /// Capabilities of a registered provider, available before a client is built.
+    pub fn capabilities(&self, name: &str) -> Option<ProviderCapabilities> {
+        self.providers
+            .get(&name.to_lowercase())
+            .map(|entry| entry.capabilities)
+    }
+
+    pub fn create(&self, name: &str, config: ProviderConfig) -> Result<AIClient> {
+        match self.providers.get(&name.to_lowercase()) {
+            Some(entry) => (entry.factory)(config),
+            None => anyhow::bail!("Unsupported AI provider: {}", name),
+        }
+    }
+
+    /// Whether the named provider needs an API key. Unknown providers are assumed to.
+    pub fn requires_api_key(&self, name: &str) -> bool {
+        self.capabilities(name)
+            .map(|capabilities| capabilities.requires_api_key)
+            .unwrap_or(true)
+    }
+
+    /// Creates a client for an ordered chain of `(provider, config)` pairs.
+    ///
+    /// A single entry yields that provider directly; longer chains are wrapped in
+    /// a [`fallback::FallbackClient`] that moves down the list on failure.
+    pub fn create_chain(&self, chain: Vec<(String, ProviderConfig)>) -> Result<AIClient> {
+        let mut clients = chain
+            .into_iter()
+            .map(|(provider, config)| self.create(&provider, config))
+            .collect::<Result<Vec<_>>>()?;
+
+        match clients.len() {
+            0 => anyhow::bail!("No AI providers configured"),
+            1 => Ok(clients.remove(0)),
+            _ => Ok(Box::new(fallback::FallbackClient::new(clients))),
         }
     }
 }
 
-pub fn create_client(
-    provider: &str,
-    api_key: String,
-    model: String,
-    base_url: Option<String>,
-) -> Result<AIClient> {
-    match provider.to_lowercase().as_str() {
-        "openai" => Ok(AIClient::OpenAI(openai::OpenAIClient::new(
-            api_key, model, base_url,
-        ))),
-        "anthropic" => Ok(AIClient::Anthropic(anthropic::AnthropicClient::new(
-            api_key, model, base_url,
-        ))),
-        _ => anyhow::bail!("Unsupported AI provider: {}", provider),
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::with_builtin()
     }
 }
 
+/// The full conversation for a request: the initial prompt, then one
+/// assistant/user pair for every round of feedback.
+pub fn build_conversation(
+    diff: &str,
+    context: &CommitContext,
+    options: &GenerationOptions,
+) -> Vec<ChatMessage> {
+    let prompt = if options.summaries.is_empty() {
+        build_prompt(diff, context)
+    } else {
+        build_summary_prompt(diff, &options.summaries, context)
+    };
+    let mut messages = vec![ChatMessage {
+        role: Role::User,
+        content: prompt,
+    }];
+
+    for turn in &options.feedback {
+        messages.push(ChatMessage {
+            role: Role::Assistant,
+            content: serde_json::to_string(&turn.previous).unwrap_or_default(),
+        });
+        messages.push(ChatMessage {
+            role: Role::User,
+            content: build_feedback_prompt(&turn.instruction),
+        });
+    }
+
+    messages
+}
+
+fn build_feedback_prompt(instruction: &str) -> String {
+    format!(
+        r#"Revise the commit message above according to this instruction:
+
+{}
+
+Keep everything the instruction doesn't ask to change, keep the Chinese and English fields consistent with each other, and respond with the complete JSON object in the same format."#,
+        instruction.trim()
+    )
+}
+
 pub fn build_prompt(diff: &str, context: &CommitContext) -> String {
     format!(
         r#"You are a Git commit message generator. Based on the following git diff, generate a bilingual (Chinese and English) structured commit message.
@@ -170,14 +577,82 @@ Context:
 - Files changed: {}
 - Lines added: {}
 - Lines removed: {}
-
+{}
 Git Diff:
 ```
 {}
 ```
 
-Generate a commit message following the Conventional Commits specification with bilingual format:
-- type: feat, fix, docs, style, refactor, test, chore, perf
+{}"#,
+        context.branch_name.as_deref().unwrap_or("unknown"),
+        context.file_count,
+        context.added_lines,
+        context.removed_lines,
+        previous_message_section(context),
+        diff,
+        output_instructions()
+    )
+}
+
+/// Prompt for the reduce step of a summarised diff: `overview` lists every
+/// changed file, `summaries` describe the parts of the diff one by one.
+fn build_summary_prompt(overview: &str, summaries: &[String], context: &CommitContext) -> String {
+    format!(
+        r#"You are a Git commit message generator. The diff for this commit was too large to send at once, so it was split into parts and each part was summarised separately. Based on the file overview and the part summaries below, generate a single bilingual (Chinese and English) structured commit message that describes the change as a whole.
+
+Context:
+- Branch: {}
+- Files changed: {}
+- Lines added: {}
+- Lines removed: {}
+{}
+Changed files:
+```
+{}
+```
+
+Summaries of each part:
+{}
+
+{}"#,
+        context.branch_name.as_deref().unwrap_or("unknown"),
+        context.file_count,
+        context.added_lines,
+        context.removed_lines,
+        previous_message_section(context),
+        overview,
+        summaries.join("\n"),
+        output_instructions()
+    )
+}
+
+/// Tells the model which message it is replacing when amending, so the
+/// rewrite keeps what still holds instead of starting from scratch.
+fn previous_message_section(context: &CommitContext) -> String {
+    match &context.previous_message {
+        Some(message) => format!(
+            r#"
+This commit amends an existing one whose current message is below. Keep its intent and any details that still apply, and update it so it describes the complete diff:
+```
+{}
+```
+"#,
+            message.trim()
+        ),
+        None => String::new(),
+    }
+}
+
+/// Output format shared by every prompt that asks for a commit message.
+fn output_instructions() -> String {
+    format!(
+        "Generate a commit message following the Conventional Commits specification with bilingual format:\n{}\n\nRespond with a JSON object containing these fields. Example:\n{}",
+        MESSAGE_FIELDS, MESSAGE_EXAMPLE
+    )
+}
+
+/// The fields of a commit message and the rules for filling them in.
+const MESSAGE_FIELDS: &str = r#"- type: feat, fix, docs, style, refactor, test, chore, perf
 - scope: optional, the component or area affected
 - description: 中文简要描述（50字符以内）
 - description_en: English brief description (50 chars or less)
@@ -189,10 +664,9 @@ Important requirements:
 1. description should be in Chinese, description_en should be its English translation
 2. body and body_en should be arrays of strings, each element is one point
 3. Each Chinese point in body should have a corresponding English translation in body_en
-4. Keep descriptions concise and clear
+4. Keep descriptions concise and clear"#;
 
-Respond with a JSON object containing these fields. Example:
-{{
+const MESSAGE_EXAMPLE: &str = r#"{
     "type": "feat",
     "scope": "auth",
     "description": "添加用户认证功能",
@@ -200,25 +674,49 @@ Respond with a JSON object containing these fields. Example:
     "body": ["实现了JWT令牌验证", "添加了用户登录接口", "集成了OAuth2.0支持"],
     "body_en": ["Implement JWT token validation", "Add user login endpoint", "Integrate OAuth2.0 support"],
     "breaking_change": null
-}}
-"#,
-        context.branch_name.as_deref().unwrap_or("unknown"),
-        context.file_count,
-        context.added_lines,
-        context.removed_lines,
-        truncate_diff(diff, 3000)
-    )
 }
+"#;
 
-fn truncate_diff(diff: &str, max_chars: usize) -> &str {
-    if diff.len() <= max_chars {
-        diff
-    } else {
-        // Find the char boundary at or before max_chars
-        let mut boundary = max_chars;
-        while !diff.is_char_boundary(boundary) && boundary > 0 {
-            boundary -= 1;
-        }
-        &diff[..boundary]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_providers_are_reachable() {
+        let mut registry = ProviderRegistry::with_builtin();
+        registry.register(
+            "InHouse",
+            ProviderCapabilities {
+                requires_api_key: false,
+                ..Default::default()
+            },
+            |config| {
+                Ok(Box::new(stub::StubProvider::new(&config.model, |_| {
+                    Ok(stub::message_json("x"))
+                })))
+            },
+        );
+
+        assert!(!registry.requires_api_key("inhouse"));
+        assert!(registry.requires_api_key("openai"));
+        assert!(registry.requires_api_key("unknown"));
+
+        let config = ProviderConfig {
+            model: "house-model".to_string(),
+            ..Default::default()
+        };
+        let client = registry
+            .create_chain(vec![("inhouse".to_string(), config.clone())])
+            .unwrap();
+        assert_eq!(client.name(), "house-model");
+
+        let chain = registry
+            .create_chain(vec![
+                ("inhouse".to_string(), config.clone()),
+                ("inhouse".to_string(), config),
+            ])
+            .unwrap();
+        assert_eq!(chain.name(), "house-model → house-model");
+        assert!(registry.create_chain(vec![]).is_err());
     }
 }
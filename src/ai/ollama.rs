@@ -0,0 +1,330 @@
+use super::error::{ErrorKind, ProviderError};
+use super::parse::parse_commit_message;
+use super::retry::RetryPolicy;
+use super::{
+    build_conversation, ChatMessage, CommitContext, CommitMessage, CommitMessageProvider,
+    GenerationOptions, ProviderCapabilities, ResponseSchema, DEFAULT_TEMPERATURE,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const DEFAULT_HOST: &str = "http://localhost:11434";
+
+/// Client for a local Ollama server. Diffs never leave the configured host.
+pub struct OllamaClient {
+    api_key: Option<String>,
+    model: String,
+    host: String,
+    client: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+impl OllamaClient {
+    pub const CAPABILITIES: ProviderCapabilities = ProviderCapabilities {
+        requires_api_key: false,
+        json_mode: true,
+        multiple_choices: false,
+    };
+
+    /// Host resolution order: `base_url`, then `OLLAMA_HOST`, then localhost.
+    /// The API key is optional and only sent when Ollama sits behind a proxy.
+    pub fn new(api_key: Option<String>, model: String, base_url: Option<String>) -> Self {
+        // Local models can take a while to load and answer, so allow more time
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(120))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        let host = base_url
+            .or_else(|| std::env::var("OLLAMA_HOST").ok())
+            .unwrap_or_else(|| DEFAULT_HOST.to_string());
+
+        Self {
+            api_key,
+            model,
+            host: normalize_host(&host),
+            client,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+/// Accepts `127.0.0.1:11434` as well as full URLs, like the Ollama CLI does.
+fn normalize_host(host: &str) -> String {
+    let host = host.trim().trim_end_matches('/');
+    if host.starts_with("http://") || host.starts_with("https://") {
+        host.to_string()
+    } else {
+        format!("http://{}", host)
+    }
+}
+
+#[async_trait]
+impl CommitMessageProvider for OllamaClient {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        Self::CAPABILITIES
+    }
+
+    async fn generate_commit_message(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<CommitMessage> {
+        let conversation = build_conversation(diff, context, options);
+        let content = self
+            .generate_structured(
+                &conversation,
+                &CommitMessage::response_schema(),
+                options,
+                debug,
+            )
+            .await?;
+        // format=json constrains the output to JSON, but older models still add prose
+        parse_commit_message(&content)
+            .context("Failed to parse commit message from Ollama response")
+    }
+
+    /// Ollama only constrains the reply to JSON, not to `schema`.
+    async fn generate_structured(
+        &self,
+        conversation: &[ChatMessage],
+        _schema: &ResponseSchema,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<String> {
+        let request = OllamaRequest {
+            model: self.model.clone(),
+            messages: std::iter::once(OllamaMessage {
+                role: "system".to_string(),
+                content: "You are a helpful assistant that generates git commit messages in JSON format. Reply with exactly one valid JSON object.".to_string(),
+            })
+            .chain(conversation.iter().map(|message| OllamaMessage {
+                role: message.role.as_str().to_string(),
+                content: message.content.clone(),
+            }))
+            .collect(),
+            stream: false,
+            format: "json".to_string(),
+            options: OllamaOptions {
+                temperature: options.temperature.unwrap_or(DEFAULT_TEMPERATURE),
+            },
+        };
+
+        let mut builder = self
+            .client
+            .post(format!("{}/api/chat", self.host))
+            .json(&request);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let response = self
+            .retry
+            .send(self.name(), builder)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to connect to Ollama at {}. Is `ollama serve` running?",
+                    self.host
+                )
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await?;
+
+            let safe_error = match status.as_u16() {
+                401 | 403 => {
+                    "Access denied by the Ollama host. Please check your proxy credentials."
+                }
+                404 => "Model not found. Run `ollama pull <model>` first.",
+                500..=599 => "Ollama server error. Please check the server logs.",
+                _ => "Request failed. Please check your configuration.",
+            };
+
+            if debug {
+                eprintln!("Debug: Full error response: {}", error_text);
+            }
+
+            return Err(ProviderError::from_status(status, safe_error).into());
+        }
+
+        let response_text = response
+            .text()
+            .await
+            .context("Failed to read response text")?;
+
+        if debug {
+            eprintln!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
+            eprintln!("{}", response_text);
+            eprintln!("{}", "=================================\n".cyan().bold());
+        }
+
+        let api_response: OllamaResponse =
+            serde_json::from_str(&response_text).context("Failed to parse Ollama response")?;
+
+        let content = api_response.message.content;
+
+        if debug {
+            eprintln!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
+            eprintln!("{}", content);
+            eprintln!("{}", "==================================\n".cyan().bold());
+        }
+
+        if api_response.done_reason.as_deref() == Some("length") {
+            return Err(ProviderError::new(ErrorKind::Parse, "AI response was truncated before completing the JSON (done_reason=length). Try reducing the diff size or switching models.").into());
+        }
+
+        Ok(content)
+    }
+}
+
+#[derive(Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    format: String,
+    options: OllamaOptions,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OllamaMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct OllamaOptions {
+    temperature: f32,
+}
+
+#[derive(Deserialize)]
+struct OllamaResponse {
+    message: OllamaMessage,
+    done_reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::error::error_kind;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Answers one HTTP request with `reply` and hands back the request.
+    async fn serve_once(reply: String) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("127.0.0.1:{}", listener.local_addr().unwrap().port());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = socket.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length || read == 0 {
+                        break;
+                    }
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                reply.len(),
+                reply
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (host, server)
+    }
+
+    fn context() -> CommitContext {
+        CommitContext {
+            branch_name: Some("main".to_string()),
+            file_count: 1,
+            added_lines: 1,
+            removed_lines: 0,
+            previous_message: None,
+        }
+    }
+
+    fn chat_reply(content: &str, done_reason: &str) -> String {
+        serde_json::json!({
+            "model": "llama3",
+            "message": { "role": "assistant", "content": content },
+            "done": true,
+            "done_reason": done_reason
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn posts_a_json_chat_request_and_parses_the_reply() {
+        let content = serde_json::json!({
+            "type": "fix",
+            "scope": "git",
+            "description": "修复暂存",
+            "description_en": "Fix staging",
+            "body": [],
+            "body_en": [],
+            "breaking_change": null
+        });
+        let (host, server) = serve_once(chat_reply(&content.to_string(), "stop")).await;
+
+        let client = OllamaClient::new(None, "llama3".to_string(), Some(host));
+        let message = client
+            .generate_commit_message("diff", &context(), &GenerationOptions::default(), false)
+            .await
+            .unwrap();
+        assert_eq!(message.header(), "fix(git): 修复暂存");
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /api/chat "));
+        assert!(!request.to_lowercase().contains("authorization:"));
+        let body: serde_json::Value =
+            serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap();
+        assert_eq!(body["model"], "llama3");
+        assert_eq!(body["format"], "json");
+        assert_eq!(body["stream"], false);
+    }
+
+    #[tokio::test]
+    async fn truncated_replies_are_parse_errors() {
+        let (host, server) = serve_once(chat_reply("{\"type\": \"fe", "length")).await;
+
+        let client = OllamaClient::new(None, "llama3".to_string(), Some(host));
+        let err = client
+            .generate_commit_message("diff", &context(), &GenerationOptions::default(), false)
+            .await
+            .unwrap_err();
+        assert_eq!(error_kind(&err), ErrorKind::Parse);
+        assert!(err.to_string().contains("done_reason=length"));
+        server.await.unwrap();
+    }
+}
@@ -1,42 +1,209 @@
-use super::{build_prompt, CommitContext, CommitMessage};
+use super::error::{ErrorKind, ProviderError};
+use super::parse::parse_commit_message;
+use super::retry::RetryPolicy;
+use super::{
+    build_conversation, ChatMessage, CommitContext, CommitMessage, CommitMessageProvider,
+    GenerationOptions, ProviderCapabilities, ResponseSchema, DEFAULT_TEMPERATURE,
+};
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use colored::*;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+/// How the API key is sent with each request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthHeader {
+    /// `Authorization: Bearer <key>` (OpenAI, most proxies, Azure AD tokens)
+    Bearer,
+    /// `api-key: <key>` (Azure OpenAI key authentication)
+    ApiKey,
+}
+
+impl AuthHeader {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "bearer" => Ok(AuthHeader::Bearer),
+            "api-key" | "api_key" => Ok(AuthHeader::ApiKey),
+            _ => anyhow::bail!(
+                "Unsupported auth_header '{}'. Use \"bearer\" or \"api-key\".",
+                value
+            ),
+        }
+    }
+}
+
 pub struct OpenAIClient {
+    provider_name: &'static str,
     api_key: String,
     model: String,
-    base_url: String,
+    chat_url: String,
+    auth_header: AuthHeader,
     client: reqwest::Client,
+    retry: RetryPolicy,
 }
 
 impl OpenAIClient {
+    pub const CAPABILITIES: ProviderCapabilities = ProviderCapabilities {
+        requires_api_key: true,
+        json_mode: true,
+        multiple_choices: true,
+    };
+
     pub fn new(api_key: String, model: String, base_url: Option<String>) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .connect_timeout(Duration::from_secs(10))
-            .build()
-            .expect("Failed to create HTTP client");
+        let base_url = base_url.unwrap_or_else(|| "https://api.openai.com/v1".to_string());
+
+        Self {
+            provider_name: "openai",
+            api_key,
+            model,
+            chat_url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            auth_header: AuthHeader::Bearer,
+            client: Self::http_client(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Client for an Azure OpenAI deployment.
+    ///
+    /// `endpoint` is the resource URL, e.g. `https://my-resource.openai.azure.com`.
+    /// Azure routes by deployment, so the deployment falls back to the model name.
+    pub fn azure(
+        api_key: String,
+        model: String,
+        endpoint: &str,
+        deployment: Option<String>,
+        api_version: Option<String>,
+    ) -> Self {
+        let deployment = deployment.unwrap_or_else(|| model.clone());
+        let api_version = api_version.unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string());
 
         Self {
+            provider_name: "azure",
             api_key,
             model,
-            base_url: base_url.unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
-            client,
+            chat_url: format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                endpoint.trim_end_matches('/'),
+                deployment,
+                api_version
+            ),
+            auth_header: AuthHeader::ApiKey,
+            client: Self::http_client(),
+            retry: RetryPolicy::default(),
         }
     }
 
-    pub async fn generate_commit_message(
+    pub fn with_auth_header(mut self, auth_header: AuthHeader) -> Self {
+        self.auth_header = auth_header;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn http_client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to create HTTP client")
+    }
+}
+
+#[async_trait]
+impl CommitMessageProvider for OpenAIClient {
+    fn name(&self) -> &str {
+        self.provider_name
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        Self::CAPABILITIES
+    }
+
+    async fn generate_commit_message(
         &self,
         diff: &str,
         context: &CommitContext,
+        options: &GenerationOptions,
         debug: bool,
     ) -> Result<CommitMessage> {
-        let prompt = build_prompt(diff, context);
+        let mut messages = self
+            .generate_candidates(diff, context, options, 1, debug)
+            .await?;
+        Ok(messages.remove(0))
+    }
+
+    /// Uses the `n` parameter so all candidates come from a single request.
+    async fn generate_candidates(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        options: &GenerationOptions,
+        count: u32,
+        debug: bool,
+    ) -> Result<Vec<CommitMessage>> {
+        let conversation = build_conversation(diff, context, options);
+        self.request_choices(
+            &conversation,
+            &CommitMessage::response_schema(),
+            options,
+            count,
+            // With json_schema the content is already a valid object; the
+            // tolerant parser only matters for endpoints that ignore it
+            |content| {
+                parse_commit_message(content)
+                    .context("Failed to parse commit message from OpenAI response")
+            },
+            debug,
+        )
+        .await
+    }
 
-        let mut max_tokens = 500;
+    async fn generate_structured(
+        &self,
+        conversation: &[ChatMessage],
+        schema: &ResponseSchema,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<String> {
+        let mut replies = self
+            .request_choices(
+                conversation,
+                schema,
+                options,
+                1,
+                |content| Ok(content.to_string()),
+                debug,
+            )
+            .await?;
+        Ok(replies.remove(0))
+    }
+}
+
+impl OpenAIClient {
+    /// Sends one chat completion request for `n` choices and returns every
+    /// choice that `parse` accepted. Truncated output is retried with a
+    /// larger token budget when no choice made it through.
+    async fn request_choices<T>(
+        &self,
+        conversation: &[ChatMessage],
+        schema: &ResponseSchema,
+        options: &GenerationOptions,
+        n: u32,
+        parse: impl Fn(&str) -> Result<T> + Send + Sync,
+        debug: bool,
+    ) -> Result<Vec<T>> {
+        let mut max_tokens = schema.max_tokens;
+        // Truncated replies may grow the budget up to eight times
+        let max_tokens_limit = schema.max_tokens.saturating_mul(8);
         let max_attempts = 4;
+        // Some OpenAI-compatible endpoints reject json_schema; fall back to json_object
+        let mut use_json_schema = true;
 
         for attempt in 0..max_attempts {
             let mut messages = vec![Message {
@@ -47,31 +214,39 @@ impl OpenAIClient {
             if attempt > 0 {
                 messages.push(Message {
                     role: "system".to_string(),
-                    content: "Your previous answer was truncated. Send the complete JSON object this time, keep it under 600 characters, and avoid any commentary or markdown fences.".to_string(),
+                    content: "Your previous answer was truncated. Send the complete JSON object this time, keep it as short as the format allows, and avoid any commentary or markdown fences.".to_string(),
                 });
             }
 
-            messages.push(Message {
-                role: "user".to_string(),
-                content: prompt.clone(),
-            });
+            messages.extend(conversation.iter().map(|message| Message {
+                role: message.role.as_str().to_string(),
+                content: message.content.clone(),
+            }));
 
             let request = OpenAIRequest {
                 model: self.model.clone(),
                 messages,
-                temperature: 0.7,
+                temperature: options.temperature.unwrap_or(DEFAULT_TEMPERATURE),
                 max_tokens,
-                response_format: Some(ResponseFormat {
-                    type_field: "json_object".to_string(),
+                n: (n > 1).then_some(n),
+                response_format: Some(if use_json_schema {
+                    ResponseFormat::json_schema(schema)
+                } else {
+                    ResponseFormat::json_object()
                 }),
             };
 
+            let builder = self.client.post(&self.chat_url);
+            let builder = match self.auth_header {
+                AuthHeader::Bearer => {
+                    builder.header("Authorization", format!("Bearer {}", self.api_key))
+                }
+                AuthHeader::ApiKey => builder.header("api-key", &self.api_key),
+            };
+
             let response = self
-                .client
-                .post(format!("{}/chat/completions", self.base_url))
-                .header("Authorization", format!("Bearer {}", self.api_key))
-                .json(&request)
-                .send()
+                .retry
+                .send(self.name(), builder.json(&request))
                 .await
                 .context("Failed to send request to OpenAI")?;
 
@@ -79,6 +254,20 @@ impl OpenAIClient {
                 let status = response.status();
                 let error_text = response.text().await?;
 
+                if status.as_u16() == 400 && use_json_schema && attempt + 1 < max_attempts {
+                    if debug {
+                        eprintln!("Debug: Full error response: {}", error_text);
+                        eprintln!(
+                            "{}",
+                            "=== DEBUG: json_schema rejected, retrying with json_object ==="
+                                .cyan()
+                                .bold()
+                        );
+                    }
+                    use_json_schema = false;
+                    continue;
+                }
+
                 // Sanitize error message to avoid exposing sensitive details
                 let safe_error = match status.as_u16() {
                     401 => "Authentication failed. Please check your API key.",
@@ -92,7 +281,7 @@ impl OpenAIClient {
                     eprintln!("Debug: Full error response: {}", error_text);
                 }
 
-                anyhow::bail!("{} (Status: {})", safe_error, status);
+                return Err(ProviderError::from_status(status, safe_error).into());
             }
 
             let response_text = response
@@ -101,127 +290,60 @@ impl OpenAIClient {
                 .context("Failed to read response text")?;
 
             if debug {
-                println!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
-                println!("{}", response_text);
-                println!("{}", "=================================\n".cyan().bold());
+                eprintln!("\n{}", "=== DEBUG: Raw HTTP Response ===".cyan().bold());
+                eprintln!("{}", response_text);
+                eprintln!("{}", "=================================\n".cyan().bold());
             }
 
             let api_response: OpenAIResponse =
                 serde_json::from_str(&response_text).context("Failed to parse OpenAI response")?;
 
-            let choice = api_response
-                .choices
-                .first()
-                .ok_or_else(|| anyhow::anyhow!("No response from OpenAI"))?;
-
-            let content = choice
-                .message
-                .content
-                .clone()
-                .ok_or_else(|| anyhow::anyhow!("Response content is null"))?;
-
-            if debug {
-                println!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
-                println!("{}", content);
-                println!("{}", "==================================\n".cyan().bold());
+            if api_response.choices.is_empty() {
+                anyhow::bail!("No response from OpenAI");
             }
 
-            match choice.finish_reason.as_deref() {
-                Some("length") => {
-                    if content.trim().is_empty() && attempt + 1 == max_attempts {
-                        anyhow::bail!("AI response was truncated repeatedly, resulting in empty content. Try reducing the diff size or switching models.");
-                    }
-
-                    if attempt + 1 < max_attempts {
-                        max_tokens = (max_tokens.saturating_mul(2)).min(4000);
-                        if debug {
-                            println!(
-                                "{}",
-                                format!(
-                                    "=== DEBUG: finish_reason=length, retrying with max_tokens={} ===",
-                                    max_tokens
-                                )
-                                .cyan()
-                                .bold()
-                            );
-                        }
-                        continue;
-                    } else {
-                        anyhow::bail!("AI response was truncated before completing the JSON (finish_reason=length). Try reducing the diff size or switching models.");
-                    }
-                }
-                Some("content_filter") => {
-                    anyhow::bail!("The response was blocked by the provider's content filter.");
-                }
-                Some("stop") | Some("stop_sequence") | None => {}
-                Some(other) => {
-                    anyhow::bail!("Unexpected finish_reason '{}' from AI response.", other);
+            let mut commit_messages = Vec::new();
+            let mut truncated = None;
+            let mut last_err = None;
+            for choice in api_response.choices {
+                match parse_choice(choice, &parse, debug) {
+                    Ok(ChoiceOutcome::Message(message)) => commit_messages.push(message),
+                    Ok(ChoiceOutcome::Truncated { empty }) => truncated = Some(empty),
+                    Err(e) => last_err = Some(e),
                 }
             }
 
-            // Strip markdown code block wrapper if present
-            let clean_content = if content.starts_with("```json") && content.ends_with("```") {
-                content
-                    .strip_prefix("```json")
-                    .and_then(|s| s.strip_suffix("```"))
-                    .map(|s| s.trim())
-                    .unwrap_or(&content)
-            } else if content.starts_with("```") && content.ends_with("```") {
-                content
-                    .strip_prefix("```")
-                    .and_then(|s| s.strip_suffix("```"))
-                    .map(|s| s.trim())
-                    .unwrap_or(&content)
-            } else {
-                &content
-            };
+            if !commit_messages.is_empty() {
+                return Ok(commit_messages);
+            }
 
-            let commit_message = match serde_json::from_str::<CommitMessage>(clean_content) {
-                Ok(msg) => msg,
-                Err(primary_err) => {
-                    let mut depth = 0;
-                    let mut start_idx = None;
-                    let mut end_idx = None;
-
-                    for (idx, ch) in clean_content.char_indices() {
-                        match ch {
-                            '{' => {
-                                if depth == 0 && start_idx.is_none() {
-                                    start_idx = Some(idx);
-                                }
-                                depth += 1;
-                            }
-                            '}' => {
-                                if depth > 0 {
-                                    depth -= 1;
-                                }
-                                if depth == 0 && start_idx.is_some() {
-                                    end_idx = Some(idx + ch.len_utf8());
-                                    break;
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
+            if let Some(empty) = truncated {
+                if empty && attempt + 1 == max_attempts {
+                    return Err(ProviderError::new(ErrorKind::Parse, "AI response was truncated repeatedly, resulting in empty content. Try reducing the diff size or switching models.").into());
+                }
 
-                    if let (Some(start), Some(end)) = (start_idx, end_idx) {
-                        let json_str = &clean_content[start..end];
-                        serde_json::from_str::<CommitMessage>(json_str).with_context(|| {
+                if attempt + 1 < max_attempts {
+                    max_tokens = (max_tokens.saturating_mul(2)).min(max_tokens_limit);
+                    if debug {
+                        eprintln!(
+                            "{}",
                             format!(
-                                "Failed to parse extracted JSON from OpenAI response: {}",
-                                primary_err
+                                "=== DEBUG: finish_reason=length, retrying with max_tokens={} ===",
+                                max_tokens
                             )
-                        })?
-                    } else {
-                        return Err(anyhow::anyhow!(
-                            "Failed to parse commit message from OpenAI response: {}",
-                            primary_err
-                        ));
+                            .cyan()
+                            .bold()
+                        );
                     }
+                    continue;
+                } else {
+                    return Err(ProviderError::new(ErrorKind::Parse, "AI response was truncated before completing the JSON (finish_reason=length). Try reducing the diff size or switching models.").into());
                 }
-            };
+            }
 
-            return Ok(commit_message);
+            if let Some(e) = last_err {
+                return Err(e);
+            }
         }
 
         anyhow::bail!(
@@ -230,12 +352,60 @@ impl OpenAIClient {
     }
 }
 
+enum ChoiceOutcome<T> {
+    Message(T),
+    Truncated { empty: bool },
+}
+
+fn parse_choice<T>(
+    choice: Choice,
+    parse: impl Fn(&str) -> Result<T>,
+    debug: bool,
+) -> Result<ChoiceOutcome<T>> {
+    if let Some(refusal) = &choice.message.refusal {
+        anyhow::bail!(
+            "The model refused to generate a commit message: {}",
+            refusal
+        );
+    }
+
+    let content = choice
+        .message
+        .content
+        .ok_or_else(|| anyhow::anyhow!("Response content is null"))?;
+
+    if debug {
+        eprintln!("\n{}", "=== DEBUG: AI Message Content ===".cyan().bold());
+        eprintln!("{}", content);
+        eprintln!("{}", "==================================\n".cyan().bold());
+    }
+
+    match choice.finish_reason.as_deref() {
+        Some("length") => {
+            return Ok(ChoiceOutcome::Truncated {
+                empty: content.trim().is_empty(),
+            });
+        }
+        Some("content_filter") => {
+            anyhow::bail!("The response was blocked by the provider's content filter.");
+        }
+        Some("stop") | Some("stop_sequence") | None => {}
+        Some(other) => {
+            anyhow::bail!("Unexpected finish_reason '{}' from AI response.", other);
+        }
+    }
+
+    parse(&content).map(ChoiceOutcome::Message)
+}
+
 #[derive(Serialize)]
 struct OpenAIRequest {
     model: String,
     messages: Vec<Message>,
     temperature: f32,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
     response_format: Option<ResponseFormat>,
 }
 
@@ -249,6 +419,35 @@ struct Message {
 struct ResponseFormat {
     #[serde(rename = "type")]
     type_field: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_schema: Option<JsonSchemaFormat>,
+}
+
+impl ResponseFormat {
+    fn json_object() -> Self {
+        Self {
+            type_field: "json_object".to_string(),
+            json_schema: None,
+        }
+    }
+
+    fn json_schema(schema: &ResponseSchema) -> Self {
+        Self {
+            type_field: "json_schema".to_string(),
+            json_schema: Some(JsonSchemaFormat {
+                name: schema.name.to_string(),
+                strict: true,
+                schema: schema.json_schema.clone(),
+            }),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonSchemaFormat {
+    name: String,
+    strict: bool,
+    schema: serde_json::Value,
 }
 
 #[derive(Deserialize)]
@@ -265,4 +464,5 @@ struct Choice {
 #[derive(Deserialize)]
 struct ResponseMessage {
     content: Option<String>,
+    refusal: Option<String>,
 }
@@ -0,0 +1,386 @@
+//! Tolerant extraction of a `CommitMessage`, or any other JSON reply, from
+//! free-form model output.
+//!
+//! Structured output modes make the happy path a plain `serde_json` call, but
+//! proxies and smaller models still wrap the object in prose or fences, or
+//! emit JSON-ish text (trailing commas, single quotes). Everything here runs
+//! without a network so the behaviour is pinned down by the tests below.
+
+use super::error::{ErrorKind, ProviderError};
+use super::CommitMessage;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+
+/// Parses a commit message from raw model output.
+///
+/// Tries, in order: the whole text, the contents of every fenced code block
+/// (including nested fences), and every balanced `{...}` object found in
+/// those. Each object is parsed strictly first and then after repairing
+/// common JSON mistakes.
+pub fn parse_commit_message(content: &str) -> Result<CommitMessage> {
+    parse_object(content, "commit message")
+}
+
+/// Like [`parse_commit_message`], for replies of any other shape; `what`
+/// names the reply in errors.
+pub fn parse_object<T: DeserializeOwned>(content: &str, what: &str) -> Result<T> {
+    let content = content.trim();
+
+    if let Ok(value) = serde_json::from_str::<T>(content) {
+        return Ok(value);
+    }
+
+    let mut last_err = None;
+    for candidate in candidates(content) {
+        for object in json_objects(candidate) {
+            match parse_lenient(object) {
+                Ok(value) => return Ok(value),
+                Err(e) => last_err = Some(e),
+            }
+        }
+    }
+
+    let message = match last_err {
+        Some(e) => format!("Invalid {} JSON: {}", what, e),
+        None => "No JSON object found in response".to_string(),
+    };
+    Err(ProviderError::new(ErrorKind::Parse, message).into())
+}
+
+fn parse_lenient<T: DeserializeOwned>(object: &str) -> serde_json::Result<T> {
+    serde_json::from_str(object).or_else(|_| serde_json::from_str(&repair_json(object)))
+}
+
+/// Text regions worth scanning for an object, innermost fences first.
+fn candidates(text: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    for block in fenced_blocks(text) {
+        result.extend(candidates(block));
+    }
+    result.push(text);
+    result
+}
+
+/// Contents of ``` fenced blocks. A fence opened with N backticks is only
+/// closed by a run of at least N, so ```` blocks may contain ``` blocks.
+/// An unterminated fence (truncated output) runs to the end of the text.
+fn fenced_blocks(text: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+
+    while let Some(offset) = text[pos..].find("```") {
+        let open = pos + offset;
+        let fence_len = backtick_run(&text[open..]);
+        let info_len = text[open + fence_len..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(text.len() - open - fence_len);
+        let body_start = open + fence_len + info_len;
+
+        match find_closing_fence(&text[body_start..], fence_len) {
+            Some((close, close_len)) => {
+                blocks.push(text[body_start..body_start + close].trim());
+                pos = body_start + close + close_len;
+            }
+            None => {
+                blocks.push(text[body_start..].trim());
+                break;
+            }
+        }
+    }
+
+    blocks
+}
+
+fn backtick_run(text: &str) -> usize {
+    text.bytes().take_while(|&b| b == b'`').count()
+}
+
+fn find_closing_fence(text: &str, min_len: usize) -> Option<(usize, usize)> {
+    let mut pos = 0;
+    while let Some(offset) = text[pos..].find('`') {
+        let start = pos + offset;
+        let len = backtick_run(&text[start..]);
+        if len >= min_len {
+            return Some((start, len));
+        }
+        pos = start + len;
+    }
+    None
+}
+
+/// Every top-level balanced `{...}` in `text`, in order.
+///
+/// Braces inside double- or single-quoted strings are ignored, and a stray
+/// `}` outside an object is skipped rather than driving the depth negative.
+/// Quotes only start strings inside an object, so apostrophes in leading
+/// prose do not confuse the scan.
+fn json_objects(text: &str) -> Vec<&str> {
+    let mut objects = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+
+    for (idx, ch) in text.char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == q {
+                quote = None;
+            }
+            continue;
+        }
+
+        match ch {
+            '"' | '\'' if depth > 0 => quote = Some(ch),
+            '{' => {
+                if depth == 0 {
+                    start = idx;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    objects.push(&text[start..idx + ch.len_utf8()]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    objects
+}
+
+/// Rewrites JSON-ish text into JSON: single-quoted strings become
+/// double-quoted, trailing commas are dropped, raw newlines inside strings
+/// are escaped and Python literals (`None`, `True`, `False`) are converted.
+fn repair_json(input: &str) -> String {
+    let chars: Vec<char> = input.chars().collect();
+    let mut out = String::with_capacity(input.len());
+    let mut quote: Option<char> = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+
+        if let Some(q) = quote {
+            match ch {
+                '\\' if i + 1 < chars.len() => {
+                    let next = chars[i + 1];
+                    if next == '\'' {
+                        // \' is not a valid JSON escape
+                        out.push('\'');
+                    } else {
+                        out.push('\\');
+                        out.push(next);
+                    }
+                    i += 2;
+                    continue;
+                }
+                '"' if q == '\'' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if c == q => {
+                    out.push('"');
+                    quote = None;
+                }
+                c => out.push(c),
+            }
+            i += 1;
+            continue;
+        }
+
+        match ch {
+            '"' | '\'' => {
+                out.push('"');
+                quote = Some(ch);
+            }
+            ',' => {
+                let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+                if !matches!(next, Some('}') | Some(']')) {
+                    out.push(',');
+                }
+            }
+            c if c.is_ascii_alphabetic() => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| !c.is_ascii_alphanumeric())
+                    .map_or(chars.len(), |p| i + p);
+                let word: String = chars[i..end].iter().collect();
+                out.push_str(match word.as_str() {
+                    "None" => "null",
+                    "True" => "true",
+                    "False" => "false",
+                    other => other,
+                });
+                i = end;
+                continue;
+            }
+            c => out.push(c),
+        }
+        i += 1;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = r#"{"type": "feat", "scope": "auth", "description": "添加登录", "description_en": "Add login", "body": ["实现登录"], "body_en": ["Implement login"], "breaking_change": null}"#;
+
+    /// Fields in the shapes models produce besides the schema's own.
+    const LOOSE_FIELDS: &str = r#"{"commit_type": "feat", "description": "功能", "description_en": "Body string", "body": "单条说明", "breaking_change": true}"#;
+
+    /// (name, raw model output, expected `description_en`; `None` = must fail)
+    const CORPUS: &[(&str, &str, Option<&str>)] = &[
+        ("plain object", VALID, Some("Add login")),
+        (
+            "json fence",
+            "```json\n{\"type\": \"fix\", \"description\": \"修复\", \"description_en\": \"Fix crash\", \"breaking_change\": null}\n```",
+            Some("Fix crash"),
+        ),
+        (
+            "bare fence",
+            "```\n{\"type\": \"fix\", \"description\": \"修复\", \"description_en\": \"Fix bare\", \"breaking_change\": null}\n```",
+            Some("Fix bare"),
+        ),
+        (
+            "single line fence",
+            "```json{\"type\": \"fix\", \"description\": \"修复\", \"description_en\": \"Inline fence\", \"breaking_change\": null}```",
+            Some("Inline fence"),
+        ),
+        (
+            "leading prose with fence",
+            "Here's the commit message you asked for:\n\n```json\n{\"type\": \"docs\", \"description\": \"文档\", \"description_en\": \"Update docs\", \"breaking_change\": null}\n```\nLet me know if you'd like changes!",
+            Some("Update docs"),
+        ),
+        (
+            "leading and trailing prose without fence",
+            "Sure! {\"type\": \"chore\", \"description\": \"杂项\", \"description_en\": \"Bump deps\", \"breaking_change\": null} Hope this helps.",
+            Some("Bump deps"),
+        ),
+        (
+            "nested fences",
+            "````markdown\nHere you go:\n```json\n{\"type\": \"test\", \"description\": \"测试\", \"description_en\": \"Add tests\", \"breaking_change\": null}\n```\n````",
+            Some("Add tests"),
+        ),
+        (
+            "unterminated fence",
+            "```json\n{\"type\": \"perf\", \"description\": \"性能\", \"description_en\": \"Speed up diff\", \"breaking_change\": null}",
+            Some("Speed up diff"),
+        ),
+        (
+            "trailing commas",
+            "{\"type\": \"feat\", \"description\": \"功能\", \"description_en\": \"Trailing commas\", \"body\": [\"一\", \"二\",], \"body_en\": [\"one\", \"two\",], \"breaking_change\": null,}",
+            Some("Trailing commas"),
+        ),
+        (
+            "single quoted python dict",
+            "{'type': 'refactor', 'scope': None, 'description': '重构', 'description_en': 'Refactor \"parser\"', 'body': ['拆分模块'], 'body_en': ['Split module'], 'breaking_change': False}",
+            Some("Refactor \"parser\""),
+        ),
+        (
+            "single quoted with escaped apostrophe",
+            "{'type': 'fix', 'description': '修复', 'description_en': 'Don\\'t panic on empty diff', 'breaking_change': None}",
+            Some("Don't panic on empty diff"),
+        ),
+        (
+            "braces inside strings",
+            "{\"type\": \"fix\", \"description\": \"修复 {} 占位符\", \"description_en\": \"Handle `{}` and `}` in format strings\", \"body\": [\"处理 {name}\"], \"body_en\": [\"Handle {name}\"], \"breaking_change\": null}",
+            Some("Handle `{}` and `}` in format strings"),
+        ),
+        (
+            "stray closing brace before object",
+            "} oops, here it is: {\"type\": \"fix\", \"description\": \"修复\", \"description_en\": \"Stray brace\", \"breaking_change\": null}",
+            Some("Stray brace"),
+        ),
+        (
+            "braces in leading prose",
+            "The diff touches {foo, bar}. Result: {\"type\": \"feat\", \"description\": \"功能\", \"description_en\": \"Prose braces\", \"breaking_change\": null}",
+            Some("Prose braces"),
+        ),
+        (
+            "example object before real one",
+            "Schema: {\"example\": true}\nAnswer: {\"type\": \"feat\", \"description\": \"功能\", \"description_en\": \"Second object\", \"breaking_change\": null}",
+            Some("Second object"),
+        ),
+        (
+            "escaped quotes",
+            r#"{"type": "fix", "description": "修复", "description_en": "Quote \"name\" and \\ path", "breaking_change": null}"#,
+            Some("Quote \"name\" and \\ path"),
+        ),
+        (
+            "raw newline inside string",
+            "{\"type\": \"fix\", \"description\": \"修复\", \"description_en\": \"Line one\nline two\", \"breaking_change\": null}",
+            Some("Line one\nline two"),
+        ),
+        (
+            "body as string and breaking change as bool",
+            LOOSE_FIELDS,
+            Some("Body string"),
+        ),
+        ("no json at all", "I could not generate a commit message.", None),
+        (
+            "truncated object",
+            "{\"type\": \"feat\", \"description\": \"功能\", \"description_en\": \"Trunc",
+            None,
+        ),
+        (
+            "object missing required fields",
+            "{\"summary\": \"not a commit message\"}",
+            None,
+        ),
+    ];
+
+    #[test]
+    fn corpus() {
+        for (name, input, expected) in CORPUS {
+            let result = parse_commit_message(input);
+            match (expected, result) {
+                (Some(description_en), Ok(msg)) => {
+                    assert_eq!(msg.description_en, *description_en, "case: {}", name)
+                }
+                (Some(_), Err(e)) => panic!("case '{}' failed to parse: {}", name, e),
+                (None, Ok(msg)) => {
+                    panic!("case '{}' should fail, got {:?}", name, msg.description_en)
+                }
+                (None, Err(_)) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn full_message_fields() {
+        let msg = parse_commit_message(VALID).unwrap();
+        assert_eq!(msg.commit_type, "feat");
+        assert_eq!(msg.scope.as_deref(), Some("auth"));
+        assert_eq!(msg.body_en, Some(vec!["Implement login".to_string()]));
+        assert_eq!(msg.breaking_change, None);
+    }
+
+    #[test]
+    fn breaking_change_bool_is_normalised() {
+        let msg = parse_commit_message(LOOSE_FIELDS).unwrap();
+        assert_eq!(msg.body, Some(vec!["单条说明".to_string()]));
+        assert_eq!(msg.breaking_change.as_deref(), Some("Breaking change"));
+    }
+
+    #[test]
+    fn stray_closing_brace_does_not_underflow() {
+        assert_eq!(json_objects("}}} {\"a\": 1} }"), vec!["{\"a\": 1}"]);
+    }
+
+    #[test]
+    fn repair_keeps_commas_between_items() {
+        assert_eq!(
+            repair_json("{'a': [1, 2,], 'b': 'x, }',}"),
+            r#"{"a": [1, 2], "b": "x, }"}"#
+        );
+    }
+}
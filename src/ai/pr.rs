@@ -0,0 +1,295 @@
+//! Pull request titles and descriptions for `rust-commit pr`, written from
+//! the commits and the cumulative diff of a branch.
+
+use super::parse::parse_object;
+use super::{
+    ChatMessage, CommitContext, CommitMessageProvider, GenerationOptions, ResponseSchema, Role,
+};
+use crate::git::CommitInfo;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PullRequest {
+    pub title: String,
+    #[serde(default)]
+    pub title_en: String,
+    pub summary: String,
+    #[serde(default)]
+    pub summary_en: String,
+    #[serde(default)]
+    pub changes: Vec<String>,
+    #[serde(default)]
+    pub changes_en: Vec<String>,
+    #[serde(default)]
+    pub testing: Vec<String>,
+    #[serde(default)]
+    pub testing_en: Vec<String>,
+    #[serde(default, deserialize_with = "super::deserialize_breaking_change")]
+    pub breaking_change: Option<String>,
+    #[serde(default, deserialize_with = "super::deserialize_breaking_change")]
+    pub breaking_change_en: Option<String>,
+}
+
+impl PullRequest {
+    /// One line, Chinese first as in commit headers.
+    pub fn title(&self) -> String {
+        if self.title_en.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} / {}", self.title, self.title_en)
+        }
+    }
+
+    /// The description without the title, e.g. for `gh pr create --body-file`.
+    pub fn body(&self) -> String {
+        let mut body = String::new();
+
+        body.push_str("## 概述 / Summary\n\n");
+        body.push_str(self.summary.trim());
+        if !self.summary_en.trim().is_empty() {
+            body.push_str("\n\n");
+            body.push_str(self.summary_en.trim());
+        }
+
+        body.push_str("\n\n## 变更 / Changes\n\n");
+        body.push_str(&bilingual_list(&self.changes, &self.changes_en));
+
+        body.push_str("\n## 测试 / Testing\n\n");
+        body.push_str(&bilingual_list(&self.testing, &self.testing_en));
+
+        body.push_str("\n## 破坏性变更 / Breaking Changes\n\n");
+        match (&self.breaking_change, &self.breaking_change_en) {
+            (None, None) => body.push_str("无 / None\n"),
+            (zh, en) => {
+                for text in [zh, en].into_iter().flatten() {
+                    body.push_str(text.trim());
+                    body.push_str("\n\n");
+                }
+                body.truncate(body.trim_end().len() + 1);
+            }
+        }
+        body
+    }
+
+    /// Title as a heading followed by the body.
+    pub fn to_markdown(&self) -> String {
+        format!("# {}\n\n{}", self.title(), self.body())
+    }
+}
+
+/// One bullet per point, the English line under its Chinese one.
+fn bilingual_list(zh: &[String], en: &[String]) -> String {
+    let count = zh.len().max(en.len());
+    if count == 0 {
+        return "- 无 / None\n".to_string();
+    }
+    let mut list = String::new();
+    for i in 0..count {
+        let lines: Vec<&str> = [zh.get(i), en.get(i)]
+            .into_iter()
+            .flatten()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        if !lines.is_empty() {
+            list.push_str(&format!("- {}\n", lines.join("\n  ")));
+        }
+    }
+    list
+}
+
+/// Asks the provider for a pull request describing `commits` and their
+/// combined `diff` against `base`. With `options.summaries` set, `diff` is
+/// only an overview of the files, as for commit messages.
+pub async fn describe_branch(
+    client: &dyn CommitMessageProvider,
+    diff: &str,
+    commits: &[CommitInfo],
+    base: &str,
+    context: &CommitContext,
+    options: &GenerationOptions,
+    debug: bool,
+) -> Result<PullRequest> {
+    let conversation = [ChatMessage {
+        role: Role::User,
+        content: build_pr_prompt(diff, &options.summaries, commits, base, context),
+    }];
+    let reply = client
+        .generate_structured(&conversation, &response_schema(), options, debug)
+        .await?;
+    parse_object(&reply, "pull request").context("Failed to parse the pull request description")
+}
+
+fn build_pr_prompt(
+    diff: &str,
+    summaries: &[String],
+    commits: &[CommitInfo],
+    base: &str,
+    context: &CommitContext,
+) -> String {
+    let commit_list: String = commits
+        .iter()
+        .map(|commit| {
+            // Indent the body under its subject line
+            let message: Vec<String> = commit
+                .message
+                .lines()
+                .map(|line| match line {
+                    "" => String::new(),
+                    _ => format!("  {}", line),
+                })
+                .collect();
+            format!("- {} {}\n", commit.id, message.join("\n").trim_start())
+        })
+        .collect();
+    let changes = if summaries.is_empty() {
+        format!("Git Diff:\n```\n{}\n```", diff)
+    } else {
+        format!(
+            "The diff was too large to send at once; here are the changed files and summaries of its parts.\n\nChanged files:\n```\n{}\n```\n\nSummaries of each part:\n{}",
+            diff,
+            summaries.join("\n")
+        )
+    };
+
+    format!(
+        r#"You are writing a pull request for code review. Based on the commits and the combined diff of the branch below, write a bilingual (Chinese and English) pull request title and description. Describe the change as a whole rather than commit by commit, and explain why it is made where the commits say so.
+
+Context:
+- Branch: {}
+- Base: {}
+- Commits: {}
+- Files changed: {}
+- Lines added: {}
+- Lines removed: {}
+
+Commits, oldest first:
+{}
+{}
+
+Fill in these fields:
+- title: 中文标题（50字符以内）
+- title_en: English title (50 chars or less)
+- summary: 中文概述，说明改动的内容和原因（一到三句话）
+- summary_en: English summary of what changes and why (one to three sentences)
+- changes: 中文变更列表数组，每个元素是一项主要改动
+- changes_en: English change list, each element corresponds to the Chinese version
+- testing: 中文测试说明数组：新增或修改的测试，以及评审者如何验证
+- testing_en: English testing notes, each element corresponds to the Chinese version
+- breaking_change: 中文破坏性变更说明，没有则为 null
+- breaking_change_en: English description of the breaking change, or null
+
+Respond with a JSON object containing these fields. Example:
+{{
+    "title": "添加用户认证",
+    "title_en": "Add user authentication",
+    "summary": "为 API 添加基于 JWT 的登录，以便保护管理接口。",
+    "summary_en": "Add JWT-based login to the API so the admin endpoints can be protected.",
+    "changes": ["添加登录接口", "为管理路由添加认证中间件"],
+    "changes_en": ["Add a login endpoint", "Add authentication middleware to the admin routes"],
+    "testing": ["添加了令牌校验的单元测试"],
+    "testing_en": ["Add unit tests for token validation"],
+    "breaking_change": null,
+    "breaking_change_en": null
+}}
+"#,
+        context.branch_name.as_deref().unwrap_or("unknown"),
+        base,
+        commits.len(),
+        context.file_count,
+        context.added_lines,
+        context.removed_lines,
+        commit_list,
+        changes
+    )
+}
+
+fn response_schema() -> ResponseSchema {
+    let string_array = serde_json::json!({ "type": "array", "items": { "type": "string" } });
+    let nullable_string = serde_json::json!({ "type": ["string", "null"] });
+    let fields = [
+        "title",
+        "title_en",
+        "summary",
+        "summary_en",
+        "changes",
+        "changes_en",
+        "testing",
+        "testing_en",
+        "breaking_change",
+        "breaking_change_en",
+    ];
+
+    ResponseSchema {
+        name: "pull_request",
+        description: "Record the bilingual pull request title and description.",
+        json_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string" },
+                "title_en": { "type": "string" },
+                "summary": { "type": "string" },
+                "summary_en": { "type": "string" },
+                "changes": string_array,
+                "changes_en": string_array,
+                "testing": string_array,
+                "testing_en": string_array,
+                "breaking_change": nullable_string,
+                "breaking_change_en": nullable_string
+            },
+            "required": fields,
+            "additionalProperties": false
+        }),
+        max_tokens: 1500,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn body_pairs_chinese_and_english() {
+        let pr = PullRequest {
+            title: "添加认证".to_string(),
+            title_en: "Add authentication".to_string(),
+            summary: "添加登录。".to_string(),
+            summary_en: "Add login.".to_string(),
+            changes: strings(&["添加接口", "添加中间件"]),
+            changes_en: strings(&["Add endpoint"]),
+            testing: vec![],
+            testing_en: vec![],
+            breaking_change: None,
+            breaking_change_en: None,
+        };
+
+        assert_eq!(pr.title(), "添加认证 / Add authentication");
+        assert_eq!(
+            pr.body(),
+            "## 概述 / Summary\n\n添加登录。\n\nAdd login.\n\n\
+             ## 变更 / Changes\n\n- 添加接口\n  Add endpoint\n- 添加中间件\n\n\
+             ## 测试 / Testing\n\n- 无 / None\n\n\
+             ## 破坏性变更 / Breaking Changes\n\n无 / None\n"
+        );
+    }
+
+    #[test]
+    fn breaking_change_accepts_booleans() {
+        let pr: PullRequest = serde_json::from_value(serde_json::json!({
+            "title": "t",
+            "summary": "s",
+            "breaking_change": "移除了旧接口",
+            "breaking_change_en": false
+        }))
+        .unwrap();
+
+        assert!(pr
+            .body()
+            .ends_with("## 破坏性变更 / Breaking Changes\n\n移除了旧接口\n"));
+    }
+}
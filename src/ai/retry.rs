@@ -0,0 +1,277 @@
+use crate::ui::CommitUI;
+use anyhow::{Context, Result};
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Retry policy shared by all providers for rate limits and server errors.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total attempts per request, including the first one
+    pub max_attempts: u32,
+    /// Stop retrying once this much time has passed since the first attempt
+    pub deadline: Duration,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            deadline: Duration::from_secs(60),
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, deadline: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            deadline,
+            ..Self::default()
+        }
+    }
+
+    /// Sends the request, retrying on 429 and 5xx responses.
+    ///
+    /// The wait comes from `Retry-After` / `x-ratelimit-reset-*` when the
+    /// server provides it, capped at `max_delay`, otherwise from jittered
+    /// exponential backoff. When
+    /// attempts or the deadline run out, the last response is returned as-is
+    /// so the caller's usual status handling produces the error.
+    pub async fn send(&self, provider: &str, request: RequestBuilder) -> Result<Response> {
+        let started = Instant::now();
+        let mut attempt = 1;
+
+        loop {
+            let response = request
+                .try_clone()
+                .context("Request body cannot be retried")?
+                .send()
+                .await?;
+
+            let status = response.status();
+            if !is_retryable(status) || attempt >= self.max_attempts {
+                return Ok(response);
+            }
+
+            let delay = self.delay(response.headers(), attempt);
+            let remaining = self.deadline.saturating_sub(started.elapsed());
+            if delay > remaining {
+                return Ok(response);
+            }
+
+            CommitUI::show_retry(provider, status, attempt, self.max_attempts, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// The server's requested wait, capped at `max_delay`, or backoff.
+    fn delay(&self, headers: &HeaderMap, attempt: u32) -> Duration {
+        server_delay(headers)
+            .map(|delay| delay.min(self.max_delay))
+            .unwrap_or_else(|| self.backoff(attempt))
+    }
+
+    /// Exponential backoff with equal jitter: a random wait in `[d/2, d]`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        exp / 2 + exp.mul_f64(fastrand::f64() / 2.0)
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// How long the server asked us to wait, if it said so. Values that don't
+/// parse are ignored; values too large for a `Duration` saturate.
+fn server_delay(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        if let Some(delay) = from_secs(ms / 1000.0) {
+            return Some(delay);
+        }
+    }
+
+    if let Some(delay) = header("retry-after").and_then(|v| retry_after(v, SystemTime::now())) {
+        return Some(delay);
+    }
+
+    // OpenAI-style limits: wait for whichever exhausted bucket resets last
+    ["requests", "tokens"]
+        .iter()
+        .filter(|kind| {
+            !matches!(
+                header(&format!("x-ratelimit-remaining-{}", kind)),
+                Some(remaining) if remaining.trim() != "0"
+            )
+        })
+        .filter_map(|kind| header(&format!("x-ratelimit-reset-{}", kind)))
+        .filter_map(parse_reset_duration)
+        .max()
+}
+
+/// Parses durations such as `1s`, `6m0s`, `20ms`, `1h2m3.5s` or a bare
+/// number of seconds.
+fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return from_secs(secs);
+    }
+
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let seconds = match &rest[..unit_len] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total += number * seconds;
+    }
+
+    from_secs(total)
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP-date, as in
+/// `Wed, 21 Oct 2015 07:28:00 GMT`; a date in the past means no wait.
+fn retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse::<f64>() {
+        Ok(secs) => from_secs(secs),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+        }
+    }
+}
+
+/// Like `Duration::from_secs_f64`, without panicking on what a server may
+/// send: NaN is rejected, negative values are zero and values too large
+/// (including infinity) saturate.
+fn from_secs(secs: f64) -> Option<Duration> {
+    if secs.is_nan() {
+        return None;
+    }
+    Some(Duration::try_from_secs_f64(secs.max(0.0)).unwrap_or(Duration::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn parses_reset_durations() {
+        let cases = [
+            ("1s", Some(1.0)),
+            ("6m0s", Some(360.0)),
+            ("20ms", Some(0.02)),
+            ("1h2m3.5s", Some(3723.5)),
+            ("2.5", Some(2.5)),
+            ("soon", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_reset_duration(input).map(|d| d.as_secs_f64()),
+                expected,
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn retry_after_takes_precedence() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("7"));
+        headers.insert("x-ratelimit-reset-requests", HeaderValue::from_static("1m"));
+        assert_eq!(server_delay(&headers), Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn only_exhausted_buckets_count() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-ratelimit-remaining-requests",
+            HeaderValue::from_static("0"),
+        );
+        headers.insert("x-ratelimit-reset-requests", HeaderValue::from_static("2s"));
+        headers.insert(
+            "x-ratelimit-remaining-tokens",
+            HeaderValue::from_static("1200"),
+        );
+        headers.insert("x-ratelimit-reset-tokens", HeaderValue::from_static("5m"));
+        assert_eq!(server_delay(&headers), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn malformed_delays_do_not_panic() {
+        let delay_for = |name: &'static str, value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_static(value));
+            server_delay(&headers)
+        };
+        assert_eq!(delay_for("retry-after", "inf"), Some(Duration::MAX));
+        assert_eq!(delay_for("retry-after", "-inf"), Some(Duration::ZERO));
+        assert_eq!(delay_for("retry-after", "NaN"), None);
+        assert_eq!(delay_for("retry-after-ms", "1e300"), Some(Duration::MAX));
+        assert_eq!(delay_for("retry-after-ms", "nan"), None);
+        assert_eq!(
+            delay_for("x-ratelimit-reset-tokens", "99999999999999999999h"),
+            Some(Duration::MAX)
+        );
+        assert_eq!(parse_reset_duration("NaN"), None);
+
+        let policy = RetryPolicy::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("inf"));
+        assert_eq!(policy.delay(&headers, 1), policy.max_delay);
+        assert_eq!(parse_reset_duration("1e300"), Some(Duration::MAX));
+    }
+
+    #[test]
+    fn retry_after_accepts_http_dates() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:29:30 GMT", now),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after("tomorrow", now), None);
+    }
+
+    #[test]
+    fn backoff_stays_within_bounds() {
+        let policy = RetryPolicy::default();
+        for attempt in 1..10 {
+            let delay = policy.backoff(attempt);
+            assert!(delay <= policy.max_delay);
+            assert!(delay >= policy.base_delay / 2);
+        }
+    }
+}
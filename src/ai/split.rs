@@ -0,0 +1,304 @@
+//! Planning for `rust-commit split`: the provider groups the changed files
+//! into changesets, each committed separately with its own message.
+
+use super::parse::parse_object;
+use super::{
+    ChatMessage, CommitContext, CommitMessage, CommitMessageProvider, GenerationOptions,
+    ResponseSchema, Role, MESSAGE_EXAMPLE, MESSAGE_FIELDS,
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// Files that go into one commit.
+#[derive(Debug, Clone)]
+pub struct Changeset {
+    pub files: Vec<String>,
+    /// `None` when no message describes these files yet, e.g. after a file
+    /// was moved in or out
+    pub message: Option<CommitMessage>,
+}
+
+#[derive(Deserialize)]
+struct SplitPlan {
+    changesets: Vec<PlannedChangeset>,
+}
+
+#[derive(Deserialize)]
+struct PlannedChangeset {
+    files: Vec<String>,
+    message: CommitMessage,
+}
+
+/// Asks the provider to group `files` into changesets, in commit order.
+/// Every file ends up in exactly one changeset; files the model left out
+/// are collected in a final changeset without a message.
+pub async fn plan_split(
+    client: &dyn CommitMessageProvider,
+    diff: &str,
+    files: &[String],
+    context: &CommitContext,
+    debug: bool,
+) -> Result<Vec<Changeset>> {
+    let conversation = [ChatMessage {
+        role: Role::User,
+        content: build_split_prompt(diff, files, context),
+    }];
+    let reply = client
+        .generate_structured(
+            &conversation,
+            &response_schema(),
+            &GenerationOptions::default(),
+            debug,
+        )
+        .await?;
+    let plan: SplitPlan =
+        parse_object(&reply, "split plan").context("Failed to parse the split plan")?;
+    Ok(normalize(plan.changesets, files))
+}
+
+/// Moves `file` into changeset `target`, or into a new last changeset when
+/// `target` is past the end. Both changesets lose their message, and a
+/// changeset left empty is dropped.
+pub fn move_file(changesets: &mut Vec<Changeset>, file: &str, target: usize) {
+    let Some(source) = changesets
+        .iter()
+        .position(|changeset| changeset.files.iter().any(|f| f == file))
+    else {
+        return;
+    };
+    if source == target {
+        return;
+    }
+
+    changesets[source].files.retain(|f| f != file);
+    changesets[source].message = None;
+    match changesets.get_mut(target) {
+        Some(changeset) => {
+            changeset.files.push(file.to_string());
+            changeset.message = None;
+        }
+        None => changesets.push(Changeset {
+            files: vec![file.to_string()],
+            message: None,
+        }),
+    }
+    changesets.retain(|changeset| !changeset.files.is_empty());
+}
+
+/// The parts of a rendered diff that touch `files`, matched on either side
+/// of a rename.
+pub fn diff_for_files(diff: &str, files: &[String]) -> String {
+    let mut output = String::new();
+    let mut keep = false;
+    for line in diff.split_inclusive('\n') {
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            keep = paths
+                .trim_end()
+                .strip_prefix("a/")
+                .and_then(|paths| paths.split_once(" b/"))
+                .map(|(old, new)| files.iter().any(|f| f == old || f == new))
+                .unwrap_or(false);
+        } else if line.starts_with("=== ") {
+            // Section titles of the combined diff
+            keep = false;
+        }
+        if keep {
+            output.push_str(line);
+        }
+    }
+    output
+}
+
+/// Drops paths that weren't asked about or were already placed, drops
+/// empty changesets, and gathers the files the model forgot.
+fn normalize(planned: Vec<PlannedChangeset>, files: &[String]) -> Vec<Changeset> {
+    let known: HashSet<&str> = files.iter().map(String::as_str).collect();
+    let mut placed = HashSet::new();
+
+    let mut changesets: Vec<Changeset> = planned
+        .into_iter()
+        .map(|changeset| Changeset {
+            files: changeset
+                .files
+                .into_iter()
+                .filter(|file| known.contains(file.as_str()) && placed.insert(file.clone()))
+                .collect(),
+            message: Some(changeset.message),
+        })
+        .filter(|changeset| !changeset.files.is_empty())
+        .collect();
+
+    let missing: Vec<String> = files
+        .iter()
+        .filter(|file| !placed.contains(*file))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        changesets.push(Changeset {
+            files: missing,
+            message: None,
+        });
+    }
+    changesets
+}
+
+fn build_split_prompt(diff: &str, files: &[String], context: &CommitContext) -> String {
+    let file_list: String = files.iter().map(|file| format!("- {}\n", file)).collect();
+    format!(
+        r#"You are a Git commit planner. The changes below mix several unrelated edits. Split them into logical, atomic commits: put files that belong to the same change together, and order the commits so each one builds on those before it (for example a refactor before the feature that relies on it). Use a single commit if the changes really are one change.
+
+Context:
+- Branch: {}
+- Files changed: {}
+- Lines added: {}
+- Lines removed: {}
+
+Changed files (use these paths exactly; every file must appear in exactly one commit):
+{}
+Git Diff:
+```
+{}
+```
+
+Write a commit message for each commit with these fields:
+{}
+
+Respond with a JSON object with a "changesets" array, in commit order, where each element has the "files" of that commit and its "message". Example:
+{{
+    "changesets": [
+        {{
+            "files": ["src/auth.rs", "src/routes.rs"],
+            "message": {}
+        }}
+    ]
+}}
+"#,
+        context.branch_name.as_deref().unwrap_or("unknown"),
+        context.file_count,
+        context.added_lines,
+        context.removed_lines,
+        file_list,
+        diff,
+        MESSAGE_FIELDS,
+        MESSAGE_EXAMPLE.trim_end().replace('\n', "\n            ")
+    )
+}
+
+fn response_schema() -> ResponseSchema {
+    let files = serde_json::json!({ "type": "array", "items": { "type": "string" } });
+
+    ResponseSchema {
+        name: "split_plan",
+        description: "Record the planned commits, in order, with their files and messages.",
+        json_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "changesets": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "files": files,
+                            "message": CommitMessage::json_schema()
+                        },
+                        "required": ["files", "message"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["changesets"],
+            "additionalProperties": false
+        }),
+        // One message per changeset
+        max_tokens: 2000,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(description: &str) -> CommitMessage {
+        serde_json::from_value(serde_json::json!({
+            "type": "feat",
+            "scope": null,
+            "description": description,
+            "description_en": description,
+            "body": [],
+            "body_en": [],
+            "breaking_change": null
+        }))
+        .unwrap()
+    }
+
+    fn planned(files: &[&str], description: &str) -> PlannedChangeset {
+        PlannedChangeset {
+            files: files.iter().map(|f| f.to_string()).collect(),
+            message: message(description),
+        }
+    }
+
+    fn paths(files: &[&str]) -> Vec<String> {
+        files.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn normalize_places_every_file_exactly_once() {
+        let files = paths(&["a.rs", "b.rs", "c.rs", "d.rs"]);
+        let changesets = normalize(
+            vec![
+                planned(&["a.rs", "ghost.rs"], "one"),
+                planned(&["a.rs", "b.rs"], "two"),
+                planned(&["ghost.rs"], "empty"),
+            ],
+            &files,
+        );
+
+        let placed: Vec<Vec<String>> = changesets.iter().map(|c| c.files.clone()).collect();
+        assert_eq!(
+            placed,
+            [paths(&["a.rs"]), paths(&["b.rs"]), paths(&["c.rs", "d.rs"])]
+        );
+        assert!(changesets[1].message.is_some());
+        assert!(changesets[2].message.is_none());
+    }
+
+    #[test]
+    fn moving_files_clears_messages_and_drops_empty_changesets() {
+        let files = paths(&["a.rs", "b.rs", "c.rs"]);
+        let mut changesets = normalize(
+            vec![planned(&["a.rs", "b.rs"], "one"), planned(&["c.rs"], "two")],
+            &files,
+        );
+
+        move_file(&mut changesets, "b.rs", 2);
+        assert_eq!(changesets.len(), 3);
+        assert!(changesets[0].message.is_none());
+        assert!(changesets[1].message.is_some());
+        assert_eq!(changesets[2].files, ["b.rs"]);
+
+        move_file(&mut changesets, "c.rs", 0);
+        assert_eq!(changesets.len(), 2);
+        assert_eq!(changesets[0].files, ["a.rs", "c.rs"]);
+        assert_eq!(changesets[1].files, ["b.rs"]);
+    }
+
+    #[test]
+    fn diff_for_files_keeps_only_matching_sections() {
+        let diff = "=== STAGED CHANGES ===\n\n\
+                    diff --git a/a.rs b/a.rs\n--- a/a.rs\n+++ b/a.rs\n@@ -1 +1 @@\n-x\n+y\n\
+                    diff --git a/old.rs b/new.rs\nrename from old.rs\nrename to new.rs\n\
+                    \n\n=== UNSTAGED CHANGES ===\n\n\
+                    diff --git a/c.rs b/c.rs\n--- a/c.rs\n+++ b/c.rs\n@@ -1 +1 @@\n-x\n+z\n";
+
+        let only_rename = diff_for_files(diff, &paths(&["new.rs"]));
+        assert_eq!(
+            only_rename,
+            "diff --git a/old.rs b/new.rs\nrename from old.rs\nrename to new.rs\n\n\n"
+        );
+        let a_and_c = diff_for_files(diff, &paths(&["a.rs", "c.rs"]));
+        assert!(a_and_c.contains("+y\n") && a_and_c.contains("+z\n"));
+        assert!(!a_and_c.contains("==="));
+    }
+}
@@ -0,0 +1,109 @@
+//! A scripted provider for tests: replies come from a closure over the
+//! last message of the conversation instead of an HTTP service.
+
+use super::parse::parse_commit_message;
+use super::{
+    build_conversation, ChatMessage, CommitContext, CommitMessage, CommitMessageProvider,
+    GenerationOptions, ProviderCapabilities, ResponseSchema,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Mutex;
+use std::time::Duration;
+
+type Reply = Box<dyn Fn(&str) -> Result<String> + Send + Sync>;
+type Delay = Box<dyn Fn(&str) -> Duration + Send + Sync>;
+
+pub struct StubProvider {
+    name: String,
+    reply: Reply,
+    delay: Option<Delay>,
+    /// Last message of every request, in the order they were made
+    pub prompts: Mutex<Vec<String>>,
+}
+
+impl StubProvider {
+    pub fn new<F>(name: &str, reply: F) -> Self
+    where
+        F: Fn(&str) -> Result<String> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            reply: Box::new(reply),
+            delay: None,
+            prompts: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Waits `delay(prompt)` before replying, e.g. to make replies arrive
+    /// out of order.
+    pub fn with_delay<F>(mut self, delay: F) -> Self
+    where
+        F: Fn(&str) -> Duration + Send + Sync + 'static,
+    {
+        self.delay = Some(Box::new(delay));
+        self
+    }
+}
+
+/// A commit message whose description is `description`, as JSON text.
+pub fn message_json(description: &str) -> String {
+    serde_json::json!({
+        "type": "feat",
+        "scope": null,
+        "description": description,
+        "description_en": description,
+        "body": [],
+        "body_en": [],
+        "breaking_change": null
+    })
+    .to_string()
+}
+
+#[async_trait]
+impl CommitMessageProvider for StubProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::default()
+    }
+
+    async fn generate_structured(
+        &self,
+        conversation: &[ChatMessage],
+        _schema: &ResponseSchema,
+        _options: &GenerationOptions,
+        _debug: bool,
+    ) -> Result<String> {
+        let prompt = conversation
+            .last()
+            .map(|message| message.content.clone())
+            .unwrap_or_default();
+        self.prompts.lock().unwrap().push(prompt.clone());
+        if let Some(delay) = &self.delay {
+            tokio::time::sleep(delay(&prompt)).await;
+        }
+        (self.reply)(&prompt)
+    }
+
+    async fn generate_commit_message(
+        &self,
+        diff: &str,
+        context: &CommitContext,
+        options: &GenerationOptions,
+        debug: bool,
+    ) -> Result<CommitMessage> {
+        let conversation = build_conversation(diff, context, options);
+        let reply = self
+            .generate_structured(
+                &conversation,
+                &CommitMessage::response_schema(),
+                options,
+                debug,
+            )
+            .await?;
+        parse_commit_message(&reply)
+    }
+}
@@ -0,0 +1,225 @@
+//! Map step for diffs too large for one prompt: each part of the diff is
+//! summarised on its own, and the summaries stand in for the diff when the
+//! final message is generated.
+
+use super::budget::split_diff;
+use super::parse::parse_object;
+use super::{
+    ChatMessage, CommitContext, CommitMessageProvider, GenerationOptions, ResponseSchema, Role,
+};
+use anyhow::{Context, Result};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::Deserialize;
+
+/// What the provider says about one part of the diff. Only the final
+/// message is bilingual, so summaries are in English.
+#[derive(Debug, Deserialize)]
+struct PartSummary {
+    summary: String,
+    #[serde(default)]
+    points: Vec<String>,
+    #[serde(default, deserialize_with = "super::deserialize_breaking_change")]
+    breaking_change: Option<String>,
+}
+
+/// Summarises `diff` in chunks of about `chunk_size` characters, running at
+/// most `concurrency` requests at a time. Summaries come back in diff order.
+pub async fn summarize_diff(
+    client: &dyn CommitMessageProvider,
+    diff: &str,
+    context: &CommitContext,
+    chunk_size: usize,
+    concurrency: usize,
+    debug: bool,
+) -> Result<Vec<String>> {
+    let chunks = split_diff(diff, chunk_size);
+    let total = chunks.len();
+    let options = GenerationOptions::default();
+    let schema = response_schema();
+
+    stream::iter(chunks.iter().enumerate())
+        .map(|(index, chunk)| {
+            let (options, schema) = (&options, &schema);
+            async move {
+                let files = changed_files(chunk);
+                let conversation = [ChatMessage {
+                    role: Role::User,
+                    content: build_part_prompt(chunk, index + 1, total, &files, context),
+                }];
+                let summary = async {
+                    let reply = client
+                        .generate_structured(&conversation, schema, options, debug)
+                        .await?;
+                    parse_object(&reply, "diff summary")
+                }
+                .await
+                .with_context(|| format!("Failed to summarise part {}/{}", index + 1, total))?;
+                Ok(render_summary(index + 1, total, &files, &summary))
+            }
+        })
+        .buffered(concurrency.max(1))
+        .try_collect()
+        .await
+}
+
+fn changed_files(chunk: &str) -> Vec<&str> {
+    chunk
+        .lines()
+        .filter_map(|line| line.strip_prefix("diff --git "))
+        .map(|paths| paths.rsplit_once(" b/").map_or(paths, |(_, path)| path))
+        .collect()
+}
+
+fn render_summary(part: usize, total: usize, files: &[&str], summary: &PartSummary) -> String {
+    let mut rendered = format!("Part {}/{} ({}):\n", part, total, files.join(", "));
+    rendered.push_str(&format!("- {}\n", summary.summary.trim()));
+    for point in &summary.points {
+        rendered.push_str(&format!("  - {}\n", point.trim()));
+    }
+    if let Some(breaking) = &summary.breaking_change {
+        rendered.push_str(&format!("  - BREAKING CHANGE: {}\n", breaking.trim()));
+    }
+    rendered
+}
+
+fn build_part_prompt(
+    chunk: &str,
+    part: usize,
+    total: usize,
+    files: &[&str],
+    context: &CommitContext,
+) -> String {
+    format!(
+        r#"You are summarising part {} of {} of a Git diff that is too large to send at once. The summaries of all parts will later be combined into one commit message, so describe only what this part changes; do not write a commit message.
+
+Context:
+- Branch: {}
+- Files in this part: {}
+
+Git Diff:
+```
+{}
+```
+
+Fill in these fields, in English:
+- summary: one sentence on what this part changes
+- points: the notable individual changes, at most five, each a short phrase
+- breaking_change: a description if this part breaks compatibility, otherwise null
+
+Respond with a JSON object containing these fields. Example:
+{{
+    "summary": "Add JWT validation to the auth middleware",
+    "points": ["Reject expired tokens", "Read the signing key from config"],
+    "breaking_change": null
+}}
+"#,
+        part,
+        total,
+        context.branch_name.as_deref().unwrap_or("unknown"),
+        files.join(", "),
+        chunk
+    )
+}
+
+fn response_schema() -> ResponseSchema {
+    ResponseSchema {
+        name: "diff_summary",
+        description: "Record a short summary of one part of a large diff.",
+        json_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "summary": { "type": "string" },
+                "points": { "type": "array", "items": { "type": "string" } },
+                "breaking_change": { "type": ["string", "null"] }
+            },
+            "required": ["summary", "points", "breaking_change"],
+            "additionalProperties": false
+        }),
+        max_tokens: 400,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::stub::StubProvider;
+    use std::time::Duration;
+
+    fn file_diff(path: &str) -> String {
+        format!(
+            "diff --git a/{0} b/{0}\n--- a/{0}\n+++ b/{0}\n@@ -1 +1 @@\n-old\n+new\n",
+            path
+        )
+    }
+
+    #[test]
+    fn changed_files_reads_the_new_paths() {
+        let chunk = format!(
+            "{}diff --git a/old name.rs b/src/new name.rs\nsimilarity index 90%\n",
+            file_diff("src/main.rs")
+        );
+        assert_eq!(changed_files(&chunk), ["src/main.rs", "src/new name.rs"]);
+    }
+
+    #[test]
+    fn render_summary_lists_points_under_the_summary() {
+        let summary = PartSummary {
+            summary: "Add a parser".to_string(),
+            points: vec!["Handle comments".to_string()],
+            breaking_change: Some("Drops the old format".to_string()),
+        };
+        assert_eq!(
+            render_summary(2, 3, &["a.rs", "b.rs"], &summary),
+            "Part 2/3 (a.rs, b.rs):\n- Add a parser\n  - Handle comments\n  \
+             - BREAKING CHANGE: Drops the old format\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn summaries_keep_diff_order_when_replies_arrive_out_of_order() {
+        // The first part answers last
+        let client = StubProvider::new("stub", |prompt| {
+            let file = ["first.rs", "second.rs", "third.rs"]
+                .into_iter()
+                .find(|file| prompt.contains(&format!("Files in this part: {}", file)))
+                .unwrap();
+            Ok(
+                serde_json::json!({ "summary": format!("Change {}", file), "points": [] })
+                    .to_string(),
+            )
+        })
+        .with_delay(|prompt| {
+            if prompt.contains("first.rs") {
+                Duration::from_millis(100)
+            } else {
+                Duration::ZERO
+            }
+        });
+        let diff: String = ["first.rs", "second.rs", "third.rs"]
+            .into_iter()
+            .map(file_diff)
+            .collect();
+        let context = CommitContext {
+            branch_name: None,
+            file_count: 3,
+            added_lines: 3,
+            removed_lines: 3,
+            previous_message: None,
+        };
+
+        let summaries = summarize_diff(&client, &diff, &context, 1, 3, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            summaries,
+            [
+                "Part 1/3 (first.rs):\n- Change first.rs\n",
+                "Part 2/3 (second.rs):\n- Change second.rs\n",
+                "Part 3/3 (third.rs):\n- Change third.rs\n",
+            ]
+        );
+        let prompts = client.prompts.lock().unwrap();
+        assert!(prompts[0].starts_with("You are summarising part"));
+        assert!(!prompts[0].contains("description_en"));
+    }
+}
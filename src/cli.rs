@@ -38,6 +38,92 @@ pub enum Commands {
 
         #[arg(long, help = "Debug mode - show AI raw response")]
         debug: bool,
+
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u32).range(1..=10),
+            help = "Generate several candidate messages and pick one"
+        )]
+        candidates: u32,
+
+        #[arg(long, help = "Skip the pre-commit and commit-msg hooks")]
+        no_verify: bool,
+
+        #[arg(long, help = "Sign the commit even if commit.gpgsign is off")]
+        sign: bool,
+
+        #[arg(
+            long,
+            conflicts_with = "sign",
+            help = "Don't sign the commit even if commit.gpgsign is on"
+        )]
+        no_sign: bool,
+
+        #[arg(
+            long,
+            help = "Rewrite the last commit and its message from its full diff"
+        )]
+        amend: bool,
+    },
+
+    /// Split the working tree changes into several commits using AI
+    ///
+    /// Changes are grouped by whole files, so unrelated changes within one
+    /// file end up in the same commit. To split a file, commit part of it
+    /// first with `commit` and "Choose hunks to stage".
+    Split {
+        #[arg(long, help = "API key for AI service (or set OPENAI_API_KEY env var)")]
+        api_key: Option<String>,
+
+        #[arg(long, help = "AI model to use (overrides config)")]
+        model: Option<String>,
+
+        #[arg(long, help = "Custom API base URL (e.g., https://api.openai.com/v1)")]
+        base_url: Option<String>,
+
+        #[arg(long, help = "Create the planned commits without confirmation")]
+        auto: bool,
+
+        #[arg(long, help = "Debug mode - show AI raw response")]
+        debug: bool,
+
+        #[arg(long, help = "Skip the pre-commit and commit-msg hooks")]
+        no_verify: bool,
+    },
+
+    /// Write a pull request title and description for the current branch
+    Pr {
+        #[arg(
+            long,
+            help = "Branch to compare against (default: origin/HEAD, main or master)"
+        )]
+        base: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            help = "Write the description to a file instead of stdout"
+        )]
+        output: Option<PathBuf>,
+
+        #[arg(
+            long,
+            help = "Leave out the title heading, e.g. for gh pr create --body-file"
+        )]
+        body_only: bool,
+
+        #[arg(long, help = "API key for AI service (or set OPENAI_API_KEY env var)")]
+        api_key: Option<String>,
+
+        #[arg(long, help = "AI model to use (overrides config)")]
+        model: Option<String>,
+
+        #[arg(long, help = "Custom API base URL (e.g., https://api.openai.com/v1)")]
+        base_url: Option<String>,
+
+        #[arg(long, help = "Debug mode - show AI raw response")]
+        debug: bool,
     },
 
     /// Show git diff
@@ -46,6 +132,12 @@ pub enum Commands {
         staged: bool,
     },
 
+    /// Manage the prepare-commit-msg hook that fills in `git commit` messages
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
+
     /// Initialize configuration file
     Init {
         #[arg(long, help = "Create config in current directory instead of home")]
@@ -55,3 +147,27 @@ pub enum Commands {
         force: bool,
     },
 }
+
+#[derive(Subcommand, Debug)]
+pub enum HookAction {
+    /// Install the hook, chaining any existing prepare-commit-msg hook
+    Install,
+
+    /// Remove the hook and restore the one it replaced
+    Uninstall,
+
+    /// Called by git: write a generated message into the message file
+    Run {
+        #[arg(help = "Commit message file passed by git")]
+        message_file: PathBuf,
+
+        #[arg(help = "Message source passed by git (message, template, merge, squash or commit)")]
+        source: Option<String>,
+
+        #[arg(help = "Commit passed by git for amends and -c/-C")]
+        commit: Option<String>,
+
+        #[arg(long, help = "Debug mode - show AI raw response")]
+        debug: bool,
+    },
+}
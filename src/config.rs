@@ -16,6 +16,57 @@ pub struct AIConfig {
     pub api_key_env: String,
     pub api_key: Option<String>,
     pub base_url: Option<String>,
+    /// Azure OpenAI deployment name (defaults to `model`)
+    pub deployment: Option<String>,
+    /// Azure OpenAI API version
+    pub api_version: Option<String>,
+    /// Auth header style: "bearer" or "api-key"
+    pub auth_header: Option<String>,
+    /// Attempts per request on rate limits and server errors, including the first
+    #[serde(default = "default_retry_max_attempts")]
+    pub retry_max_attempts: u32,
+    /// Stop retrying after this many seconds in total
+    #[serde(default = "default_retry_deadline_secs")]
+    pub retry_deadline_secs: u64,
+    /// Alternative models offered when regenerating a message
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Providers tried in order when the primary one fails
+    #[serde(default)]
+    pub fallback: Vec<FallbackProviderConfig>,
+}
+
+/// One `[[ai.fallback]]` entry. Fields mirror the primary `[ai]` settings.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FallbackProviderConfig {
+    pub provider: String,
+    pub model: String,
+    pub api_key_env: Option<String>,
+    pub api_key: Option<String>,
+    pub base_url: Option<String>,
+    pub deployment: Option<String>,
+    pub api_version: Option<String>,
+    pub auth_header: Option<String>,
+}
+
+impl FallbackProviderConfig {
+    pub fn get_api_key(&self) -> Option<String> {
+        if let Some(key) = &self.api_key {
+            return Some(key.clone());
+        }
+
+        self.api_key_env
+            .as_ref()
+            .and_then(|env| std::env::var(env).ok())
+    }
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_deadline_secs() -> u64 {
+    60
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -24,6 +75,69 @@ pub struct CommitConfig {
     pub include_emoji: bool,
     pub max_diff_size: usize,
     pub auto_stage: bool,
+    /// Diffs larger than this many characters are summarised part by part
+    /// before the commit message is generated; 0 disables summarising
+    #[serde(default = "default_summarize_threshold")]
+    pub summarize_threshold: usize,
+    /// How many parts are summarised at the same time
+    #[serde(default = "default_summarize_concurrency")]
+    pub summarize_concurrency: usize,
+    /// Globs of files shown to the AI only as "file changed (N lines)"; they
+    /// are still committed. `.gitattributes` `linguist-generated` and `-diff`
+    /// are honoured as well
+    #[serde(default = "default_exclude")]
+    pub exclude: Vec<String>,
+    /// Extra regexes for secrets, masked alongside the built-in detectors;
+    /// when a pattern has a capture group only the group is masked
+    #[serde(default)]
+    pub secret_patterns: Vec<String>,
+    /// What to do when secrets are found: "ask", "redact", "continue" or "abort"
+    #[serde(default = "default_on_secret")]
+    pub on_secret: String,
+    /// Minimum similarity in percent for a deleted and an added file to be
+    /// reported as a rename or copy; 0 disables detection
+    #[serde(default = "default_similarity_threshold")]
+    pub similarity_threshold: u16,
+}
+
+fn default_summarize_threshold() -> usize {
+    40000
+}
+
+fn default_summarize_concurrency() -> usize {
+    4
+}
+
+fn default_similarity_threshold() -> u16 {
+    crate::git::DEFAULT_SIMILARITY_THRESHOLD
+}
+
+fn default_on_secret() -> String {
+    "ask".to_string()
+}
+
+/// Lock files and minified assets: machine-written, so excluded from the
+/// prompt by default, and sent last by `fit_diff` when `exclude` keeps them.
+/// Either a file name or `*` followed by a suffix.
+pub const GENERATED_FILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+    "*.min.js",
+    "*.min.css",
+];
+
+fn default_exclude() -> Vec<String> {
+    GENERATED_FILES
+        .iter()
+        .chain(&["vendor/**"])
+        .map(|pattern| pattern.to_string())
+        .collect()
 }
 
 impl Default for Config {
@@ -35,12 +149,25 @@ impl Default for Config {
                 api_key_env: "OPENAI_API_KEY".to_string(),
                 api_key: None,
                 base_url: None,
+                deployment: None,
+                api_version: None,
+                auth_header: None,
+                retry_max_attempts: default_retry_max_attempts(),
+                retry_deadline_secs: default_retry_deadline_secs(),
+                models: Vec::new(),
+                fallback: Vec::new(),
             },
             commit: CommitConfig {
                 format: "conventional".to_string(),
                 include_emoji: false,
                 max_diff_size: 10000,
                 auto_stage: false,
+                summarize_threshold: default_summarize_threshold(),
+                summarize_concurrency: default_summarize_concurrency(),
+                exclude: default_exclude(),
+                secret_patterns: Vec::new(),
+                on_secret: default_on_secret(),
+                similarity_threshold: default_similarity_threshold(),
             },
         }
     }
@@ -104,17 +231,23 @@ impl Config {
 # This file configures the rust-commit tool for AI-powered commit message generation
 
 [ai]
-# AI provider: "openai" or "anthropic"
+# AI provider: "openai", "azure", "anthropic", "gemini" or "ollama" (local, no API key needed)
 provider = "openai"
 
 # Model to use for generation
 # OpenAI: "gpt-4", "gpt-4-turbo", "gpt-3.5-turbo"
 # Anthropic: "claude-3-opus", "claude-3-sonnet", "claude-3-haiku"
+# Gemini: "gemini-2.5-flash", "gemini-2.5-pro"
+# Ollama: any locally pulled model, e.g. "llama3.1", "qwen2.5-coder"
 model = "gpt-4"
 
+# Alternative models offered by "Regenerate" (optional)
+# models = ["gpt-4.1", "gpt-4.1-mini", "o4-mini"]
+
 # Environment variable containing the API key
 # For OpenAI: typically "OPENAI_API_KEY"
 # For Anthropic: typically "ANTHROPIC_API_KEY"
+# For Gemini: typically "GEMINI_API_KEY"
 api_key_env = "OPENAI_API_KEY"
 
 # Direct API key (not recommended for security reasons)
@@ -126,10 +259,39 @@ api_key_env = "OPENAI_API_KEY"
 # base_url = "https://api.openai.com/v1"
 # For Anthropic-compatible APIs:
 # base_url = "https://api.anthropic.com"
+# For Azure OpenAI, the resource endpoint:
+# base_url = "https://your-resource.openai.azure.com"
+# For Gemini:
+# base_url = "https://generativelanguage.googleapis.com/v1beta"
+# For Ollama (defaults to $OLLAMA_HOST or http://localhost:11434):
+# base_url = "http://localhost:11434"
 # Examples:
 # base_url = "https://your-proxy.com/v1"  # For API proxies
 # base_url = "http://localhost:8080/v1"   # For local LLMs
 
+# Azure OpenAI settings (only used when provider = "azure")
+# deployment = "gpt-4o"          # Deployment name, defaults to `model`
+# api_version = "2024-10-21"     # The `api-version` query parameter
+
+# How the API key is sent: "bearer" (Authorization header) or "api-key"
+# Defaults to "bearer" for openai and "api-key" for azure
+# auth_header = "api-key"
+
+# Retries on rate limits (429) and server errors (5xx), honouring Retry-After
+retry_max_attempts = 3     # Attempts per request, including the first one
+retry_deadline_secs = 60   # Give up retrying after this many seconds
+
+# Fallback providers, tried in order when the one above fails with an auth,
+# rate-limit, timeout, outage or parse error
+# [[ai.fallback]]
+# provider = "openai"
+# model = "gpt-4.1"
+# api_key_env = "OPENAI_API_KEY"
+#
+# [[ai.fallback]]
+# provider = "ollama"
+# model = "llama3.1"
+
 [commit]
 # Commit message format: "conventional" (follows Conventional Commits spec)
 format = "conventional"
@@ -137,11 +299,38 @@ format = "conventional"
 # Whether to include emoji in commit messages
 include_emoji = false
 
-# Maximum diff size in characters to send to AI
+# Maximum diff size in characters to send to AI. Larger diffs keep the
+# header and a stat line of every file; hunks share the remaining space
 max_diff_size = 4000
 
 # Whether to automatically stage all changes before committing
 auto_stage = false
+
+# Diffs larger than this many characters are split into parts of
+# max_diff_size, each part is summarised, and the commit message is written
+# from the summaries (0 disables)
+summarize_threshold = 40000
+
+# How many parts are summarised concurrently
+summarize_concurrency = 4
+
+# Files whose diffs are replaced by a "file changed (N lines)" summary in the
+# prompt. They are still committed. Patterns without a '/' match at any depth.
+# Files marked linguist-generated or -diff in .gitattributes are treated the same
+exclude = ["Cargo.lock", "package-lock.json", "yarn.lock", "pnpm-lock.yaml", "poetry.lock", "Gemfile.lock", "composer.lock", "go.sum", "*.min.js", "*.min.css", "vendor/**"]
+
+# Secrets (AWS keys, private keys, JWTs, high-entropy strings, .env values)
+# are detected before the diff, and any commit messages sent with it, leave
+# the machine. What to do when some are found:
+# "ask", "redact", "continue" or "abort" ("ask" redacts with --auto)
+on_secret = "ask"
+
+# Extra regexes to treat as secrets; with a capture group only the group is masked
+# secret_patterns = ['internal_token:\s*(\w+)']
+
+# How similar (in percent) a deleted and an added file must be to be shown
+# as a rename or copy, in status and in the diff sent to the AI (0 disables)
+similarity_threshold = 50
 "#;
 
         // Create parent directory if it doesn't exist
@@ -1,52 +1,159 @@
 use anyhow::{Context, Result};
-use git2::{DiffOptions, Repository, StatusOptions};
+use git2::{
+    AttrCheckFlags, AttrValue, Delta, DiffFindOptions, DiffOptions, IndexAddOption, Oid, Patch,
+    Repository, Signature, StatusOptions,
+};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::signing::Signer;
 
 pub struct GitRepo {
     repo: Repository,
+    /// Files whose diffs are reduced to a one-line summary; `None` shows everything
+    exclude: Option<GlobSet>,
+    /// Minimum similarity (0-100) for renames and copies; 0 turns detection off
+    similarity_threshold: u16,
+}
+
+/// Per-commit overrides of what git config would otherwise decide.
+#[derive(Debug, Default)]
+pub struct CommitOptions {
+    /// Skip the `pre-commit` and `commit-msg` hooks
+    pub no_verify: bool,
+    /// Force signing on or off; `None` follows `commit.gpgsign`
+    pub sign: Option<bool>,
+    /// Replace HEAD instead of adding a commit on top of it
+    pub amend: bool,
 }
 
+/// Git's own default for `-M` and `-C`.
+pub const DEFAULT_SIMILARITY_THRESHOLD: u16 = 50;
+
 impl GitRepo {
     pub fn open(path: &std::path::Path) -> Result<Self> {
         let repo = Repository::open(path).context("Failed to open repository")?;
-        Ok(Self { repo })
+        Ok(Self {
+            repo,
+            exclude: None,
+            similarity_threshold: DEFAULT_SIMILARITY_THRESHOLD,
+        })
+    }
+
+    /// Sets how similar two files must be, in percent, to count as a rename
+    /// or copy. 0 disables detection.
+    pub fn with_similarity_threshold(mut self, threshold: u16) -> Self {
+        self.similarity_threshold = threshold.min(100);
+        self
+    }
+
+    /// Reads the staged state from `path` instead of `.git/index`, for hooks
+    /// run while git has a temporary index (`git commit -a`, `git commit <paths>`).
+    pub fn with_index_file(self, path: &Path) -> Result<Self> {
+        let mut index = git2::Index::open(path)
+            .with_context(|| format!("Failed to open index {}", path.display()))?;
+        self.repo.set_index(&mut index)?;
+        Ok(self)
+    }
+
+    /// Summarises files matching `patterns`, or marked `linguist-generated`
+    /// or `-diff` in `.gitattributes`, instead of including their diffs.
+    ///
+    /// Patterns without a `/` match at any depth, like in `.gitignore`.
+    pub fn with_exclude(mut self, patterns: &[String]) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = Glob::new(pattern)
+                .with_context(|| format!("Invalid exclude pattern '{}'", pattern))?;
+            builder.add(glob);
+            if !pattern.contains('/') {
+                builder.add(Glob::new(&format!("**/{}", pattern))?);
+            }
+        }
+        self.exclude = Some(builder.build()?);
+        Ok(self)
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        let Some(exclude) = &self.exclude else {
+            return false;
+        };
+        if exclude.is_match(path) {
+            return true;
+        }
+
+        let attr = |name| {
+            self.repo
+                .get_attr(path, name, AttrCheckFlags::default())
+                .map(AttrValue::from_string)
+                .unwrap_or(AttrValue::Unspecified)
+        };
+        let generated = match attr("linguist-generated") {
+            AttrValue::True => true,
+            AttrValue::String(value) => value == "true",
+            _ => false,
+        };
+        generated || matches!(attr("diff"), AttrValue::False)
     }
 
     pub fn get_status(&self) -> Result<GitStatus> {
         let mut status_opts = StatusOptions::new();
-        status_opts.include_untracked(true);
+        // One entry per file, so every path can be staged on its own
+        status_opts
+            .include_untracked(true)
+            .recurse_untracked_dirs(true);
         status_opts.include_ignored(false);
+        if self.similarity_threshold > 0 {
+            status_opts
+                .renames_head_to_index(true)
+                .renames_index_to_workdir(true)
+                .rename_threshold(self.similarity_threshold);
+        }
 
         let statuses = self
             .repo
             .statuses(Some(&mut status_opts))
             .context("Failed to get repository status")?;
 
-        let mut modified_files = Vec::new();
-        let mut new_files = Vec::new();
-        let mut deleted_files = Vec::new();
-        let mut renamed_files = Vec::new();
-
-        for entry in statuses.iter() {
-            let status = entry.status();
-            let path = entry.path().unwrap_or("unknown").to_string();
+        let entries = statuses
+            .iter()
+            .map(|entry| {
+                let status = entry.status();
+                // Renames report the new path; the old one is in the delta
+                let old_path = [entry.head_to_index(), entry.index_to_workdir()]
+                    .into_iter()
+                    .flatten()
+                    .filter(|delta| delta.status() == Delta::Renamed)
+                    .find_map(|delta| {
+                        delta
+                            .old_file()
+                            .path()
+                            .map(|path| path.display().to_string())
+                    });
+                let path = [entry.index_to_workdir(), entry.head_to_index()]
+                    .into_iter()
+                    .flatten()
+                    .find_map(|delta| {
+                        delta
+                            .new_file()
+                            .path()
+                            .map(|path| path.display().to_string())
+                    })
+                    .unwrap_or_else(|| entry.path().unwrap_or("unknown").to_string());
 
-            if status.is_wt_modified() || status.is_index_modified() {
-                modified_files.push(path);
-            } else if status.is_wt_new() || status.is_index_new() {
-                new_files.push(path);
-            } else if status.is_wt_deleted() || status.is_index_deleted() {
-                deleted_files.push(path);
-            } else if status.is_wt_renamed() || status.is_index_renamed() {
-                renamed_files.push(path);
-            }
-        }
+                StatusEntry {
+                    path,
+                    old_path,
+                    index: FileState::index(status),
+                    worktree: FileState::worktree(status),
+                }
+            })
+            .collect();
 
         Ok(GitStatus {
             is_clean: statuses.is_empty(),
-            modified_files,
-            new_files,
-            deleted_files,
-            renamed_files,
+            entries,
         })
     }
 
@@ -100,11 +207,15 @@ impl GitRepo {
         }
     }
 
-    pub fn get_diff(&self, staged: bool) -> Result<String> {
+    /// Staged (index vs HEAD) or unstaged (workdir vs index) changes, one
+    /// entry per file.
+    pub fn get_changes(&self, staged: bool) -> Result<Vec<FileChange>> {
         let mut diff_opts = DiffOptions::new();
-        diff_opts.include_untracked(true);
+        diff_opts
+            .include_untracked(true)
+            .recurse_untracked_dirs(true);
 
-        let diff = if staged {
+        let mut diff = if staged {
             // Get staged changes (index vs HEAD)
             match self.repo.head() {
                 Ok(head) => {
@@ -132,21 +243,545 @@ impl GitRepo {
                 .diff_index_to_workdir(None, Some(&mut diff_opts))?
         };
 
-        let mut diff_text = String::new();
-        diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
-            use git2::DiffLineType::*;
-            let prefix = match line.origin_value() {
-                Addition => "+",
-                Deletion => "-",
-                Context => " ",
-                _ => "",
+        self.changes_from(&mut diff, !staged)
+    }
+
+    /// What `git commit --amend` would commit: the last commit's own changes
+    /// plus anything staged since, i.e. its parent (or nothing, for a root
+    /// commit) against the index.
+    pub fn get_amend_changes(&self) -> Result<Vec<FileChange>> {
+        let head = self.head_commit()?;
+        let base = match head.parent_count() {
+            0 => None,
+            _ => Some(head.parent(0)?.tree()?),
+        };
+        let mut index = self.repo.index()?;
+        let index_tree = self.repo.find_tree(index.write_tree()?)?;
+
+        let mut diff_opts = DiffOptions::new();
+        let mut diff =
+            self.repo
+                .diff_tree_to_tree(base.as_ref(), Some(&index_tree), Some(&mut diff_opts))?;
+        self.changes_from(&mut diff, false)
+    }
+
+    /// The changes from [`get_amend_changes`](Self::get_amend_changes) as a unified diff.
+    pub fn get_amend_diff(&self) -> Result<String> {
+        Ok(self
+            .get_amend_changes()?
+            .iter()
+            .map(FileChange::render)
+            .collect())
+    }
+
+    /// Where the current branch forks from `base`, a branch, tag or commit.
+    /// A branch that only exists on `origin` is found as well.
+    pub fn merge_base(&self, base: &str) -> Result<Oid> {
+        let base_commit = self
+            .repo
+            .revparse_single(base)
+            .or_else(|_| self.repo.revparse_single(&format!("origin/{}", base)))
+            .with_context(|| format!("Base branch '{}' not found", base))?
+            .peel_to_commit()?;
+        let head = self
+            .repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .context("The current branch has no commits yet")?;
+        self.repo
+            .merge_base(base_commit.id(), head.id())
+            .with_context(|| format!("HEAD has no common history with '{}'", base))
+    }
+
+    /// The branch pull requests usually target: what `origin/HEAD` points
+    /// at, otherwise `main` or `master`.
+    pub fn default_base(&self) -> Option<String> {
+        let origin_head = self
+            .repo
+            .find_reference("refs/remotes/origin/HEAD")
+            .ok()
+            .and_then(|reference| reference.symbolic_target().map(str::to_string));
+        if let Some(target) = origin_head {
+            return Some(
+                target
+                    .strip_prefix("refs/remotes/")
+                    .unwrap_or(&target)
+                    .to_string(),
+            );
+        }
+        ["main", "master"]
+            .into_iter()
+            .find(|name| {
+                self.repo.revparse_single(name).is_ok()
+                    || self
+                        .repo
+                        .revparse_single(&format!("origin/{}", name))
+                        .is_ok()
+            })
+            .map(str::to_string)
+    }
+
+    /// Commits on HEAD but not on `base`, oldest first, without merges.
+    pub fn commits_since(&self, base: Oid) -> Result<Vec<CommitInfo>> {
+        let mut walk = self.repo.revwalk()?;
+        walk.push_head()?;
+        walk.hide(base)?;
+        walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+
+        let mut commits = Vec::new();
+        for oid in walk {
+            let commit = self.repo.find_commit(oid?)?;
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            commits.push(CommitInfo {
+                id: commit
+                    .as_object()
+                    .short_id()?
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+                message: String::from_utf8_lossy(commit.message_bytes())
+                    .trim()
+                    .to_string(),
+            });
+        }
+        Ok(commits)
+    }
+
+    /// Everything HEAD changes since `base`, like `git diff base...HEAD`.
+    pub fn get_range_changes(&self, base: Oid) -> Result<Vec<FileChange>> {
+        let base_tree = self.repo.find_commit(base)?.tree()?;
+        let head_tree = self.repo.head()?.peel_to_tree()?;
+        let mut diff_opts = DiffOptions::new();
+        let mut diff = self.repo.diff_tree_to_tree(
+            Some(&base_tree),
+            Some(&head_tree),
+            Some(&mut diff_opts),
+        )?;
+        self.changes_from(&mut diff, false)
+    }
+
+    /// The changes from [`get_range_changes`](Self::get_range_changes) as a unified diff.
+    pub fn get_range_diff(&self, base: Oid) -> Result<String> {
+        Ok(self
+            .get_range_changes(base)?
+            .iter()
+            .map(FileChange::render)
+            .collect())
+    }
+
+    /// Full message of the commit HEAD points at.
+    pub fn head_message(&self) -> Result<String> {
+        let head = self.head_commit()?;
+        Ok(String::from_utf8_lossy(head.message_bytes()).into_owned())
+    }
+
+    fn head_commit(&self) -> Result<git2::Commit<'_>> {
+        match self.repo.head() {
+            Ok(head) => Ok(head.peel_to_commit()?),
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => {
+                anyhow::bail!("Nothing to amend: the current branch has no commits yet")
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Runs rename/copy detection over `diff` and converts its deltas.
+    /// `for_untracked` also pairs deleted files with untracked ones.
+    fn changes_from(&self, diff: &mut git2::Diff, for_untracked: bool) -> Result<Vec<FileChange>> {
+        if self.similarity_threshold > 0 {
+            let mut find_opts = DiffFindOptions::new();
+            find_opts
+                .renames(true)
+                .copies(true)
+                .rename_threshold(self.similarity_threshold)
+                .copy_threshold(self.similarity_threshold)
+                // A `mv` that hasn't been staged shows up as deleted + untracked
+                .for_untracked(for_untracked);
+            diff.find_similar(Some(&mut find_opts))?;
+        }
+        self.convert_deltas(diff)
+    }
+
+    fn convert_deltas(&self, diff: &git2::Diff) -> Result<Vec<FileChange>> {
+        let mut changes = Vec::new();
+        for (idx, delta) in diff.deltas().enumerate() {
+            let status = match delta.status() {
+                Delta::Added | Delta::Untracked => ChangeStatus::Added,
+                Delta::Deleted => ChangeStatus::Deleted,
+                Delta::Renamed => ChangeStatus::Renamed,
+                Delta::Copied => ChangeStatus::Copied,
+                Delta::Typechange => ChangeStatus::TypeChange,
+                Delta::Modified => ChangeStatus::Modified,
+                _ => continue,
+            };
+            let path_of =
+                |file: git2::DiffFile| file.path().map(|path| path.to_string_lossy().into_owned());
+            let old_path = path_of(delta.old_file()).filter(|_| status != ChangeStatus::Added);
+            let new_path = path_of(delta.new_file()).filter(|_| status != ChangeStatus::Deleted);
+
+            let old_mode = u32::from(delta.old_file().mode());
+            let new_mode = u32::from(delta.new_file().mode());
+            let mode = if status == ChangeStatus::Deleted {
+                old_mode
+            } else {
+                new_mode
             };
-            let content = std::str::from_utf8(line.content()).unwrap_or("");
-            diff_text.push_str(&format!("{}{}", prefix, content));
-            true
-        })?;
+            let mode_change = (old_mode != 0 && new_mode != 0 && old_mode != new_mode)
+                .then_some((old_mode, new_mode));
+
+            let mut binary = delta.flags().is_binary();
+
+            let mut similarity = None;
+            let mut hunks = Vec::new();
+            if let Some(mut patch) = Patch::from_diff(diff, idx)? {
+                // Binary detection needs the content, which the patch loads
+                binary |= patch.delta().flags().is_binary();
+                if matches!(status, ChangeStatus::Renamed | ChangeStatus::Copied) {
+                    // git2 doesn't expose the score, but the patch header has it
+                    let header = patch.to_buf()?;
+                    similarity = String::from_utf8_lossy(&header)
+                        .lines()
+                        .find_map(|line| line.strip_prefix("similarity index "))
+                        .and_then(|score| score.trim_end_matches('%').parse().ok());
+                }
+
+                for hunk_idx in 0..patch.num_hunks() {
+                    let (hunk, line_count) = patch.hunk(hunk_idx)?;
+                    let mut lines = Vec::with_capacity(line_count);
+                    for line_idx in 0..line_count {
+                        let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+                        lines.push(HunkLine {
+                            origin: line.origin(),
+                            content: String::from_utf8_lossy(line.content()).into_owned(),
+                        });
+                    }
+                    hunks.push(Hunk {
+                        header: String::from_utf8_lossy(hunk.header()).into_owned(),
+                        lines,
+                    });
+                }
+            }
+
+            let excluded = self.is_excluded(Path::new(
+                new_path.as_deref().or(old_path.as_deref()).unwrap_or(""),
+            ));
+
+            changes.push(FileChange {
+                status,
+                old_path,
+                new_path,
+                similarity,
+                mode,
+                mode_change,
+                binary,
+                excluded,
+                hunks,
+            });
+        }
+
+        Ok(changes)
+    }
+
+    /// Unstaged changes to pick hunks from. There is no rename detection, so
+    /// each file can be staged on its own, and untracked files come with
+    /// their content as a single hunk.
+    pub fn get_unstaged_hunks(&self) -> Result<Vec<FileChange>> {
+        let mut diff_opts = DiffOptions::new();
+        diff_opts
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true);
+        let diff = self
+            .repo
+            .diff_index_to_workdir(None, Some(&mut diff_opts))?;
+
+        let mut changes = self.convert_deltas(&diff)?;
+        for change in &mut changes {
+            // Picking needs every line, whatever the prompt would show
+            change.excluded = false;
+        }
+        Ok(changes)
+    }
+
+    /// Applies `patch`, built from [`get_unstaged_hunks`](Self::get_unstaged_hunks)
+    /// entries, to the index only, as `git add -p` does.
+    pub fn stage_patch(&self, patch: &str) -> Result<()> {
+        let diff = git2::Diff::from_buffer(patch.as_bytes())
+            .context("Failed to parse the selected hunks")?;
+        self.repo
+            .apply(&diff, git2::ApplyLocation::Index, None)
+            .context("Failed to stage the selected hunks")?;
+        Ok(())
+    }
+
+    /// The changes from [`get_changes`](Self::get_changes) as a unified diff.
+    pub fn get_diff(&self, staged: bool) -> Result<String> {
+        Ok(self
+            .get_changes(staged)?
+            .iter()
+            .map(FileChange::render)
+            .collect())
+    }
+
+    /// Stages every change in the working tree, like `git add -A`.
+    /// Ignored files stay unstaged.
+    pub fn stage_all(&self) -> Result<()> {
+        let mut index = self.repo.index()?;
+        index
+            .add_all(["*"], IndexAddOption::DEFAULT, None)
+            .context("Failed to stage changes")?;
+        // add_all doesn't notice deletions
+        index.update_all(["*"], None)?;
+        index.write().context("Failed to write the index")?;
+        Ok(())
+    }
+
+    /// Stages `paths` from the working tree, on top of what is already
+    /// staged. Paths gone from the working tree are staged as deletions.
+    pub fn stage_paths(&self, paths: &[String]) -> Result<()> {
+        let mut index = self.repo.index()?;
+        self.add_paths(&mut index, paths)?;
+        index.write().context("Failed to write the index")?;
+        Ok(())
+    }
+
+    /// Resets the index to HEAD and stages `paths` from the working tree,
+    /// so the next commit contains exactly those files.
+    pub fn stage_only(&self, paths: &[String]) -> Result<()> {
+        let mut index = self.repo.index()?;
+        match self.repo.head() {
+            Ok(head) => index.read_tree(&head.peel_to_tree()?)?,
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => index.clear()?,
+            Err(e) => return Err(e.into()),
+        }
+        self.add_paths(&mut index, paths)?;
+        index.write().context("Failed to write the index")?;
+        Ok(())
+    }
+
+    /// The staged tree, to put back with [`restore_index`](Self::restore_index).
+    pub fn index_tree(&self) -> Result<Oid> {
+        Ok(self.repo.index()?.write_tree()?)
+    }
+
+    /// Makes the index match `tree` again, e.g. after a failed [`stage_only`](Self::stage_only).
+    pub fn restore_index(&self, tree: Oid) -> Result<()> {
+        let mut index = self.repo.index()?;
+        index.read_tree(&self.repo.find_tree(tree)?)?;
+        index.write().context("Failed to write the index")?;
+        Ok(())
+    }
+
+    fn add_paths(&self, index: &mut git2::Index, paths: &[String]) -> Result<()> {
+        let workdir = self.workdir()?;
+        for path in paths {
+            let path = Path::new(path);
+            if workdir.join(path).symlink_metadata().is_ok() {
+                index
+                    .add_path(path)
+                    .with_context(|| format!("Failed to stage {}", path.display()))?;
+            } else {
+                index.remove_path(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits the index to HEAD, the way `git commit -m` would.
+    ///
+    /// - Hooks: `pre-commit`, `prepare-commit-msg`, `commit-msg` and
+    ///   `post-commit` from `core.hooksPath` (or `.git/hooks`) are run here,
+    ///   since libgit2 doesn't. `no_verify` skips `pre-commit` and
+    ///   `commit-msg`, like `--no-verify`.
+    /// - Identity: `GIT_AUTHOR_*` / `GIT_COMMITTER_*` name and email take
+    ///   precedence over `user.name` / `user.email`.
+    /// - Signing: follows `commit.gpgsign` unless `options.sign` overrides
+    ///   it, using the signer from `gpg.format` and `user.signingkey`.
+    /// - Amending: with `options.amend`, HEAD is replaced by a commit with
+    ///   the same parents and author, as `git commit --amend` does.
+    pub fn commit(&self, message: &str, options: &CommitOptions) -> Result<Oid> {
+        let config = self.repo.config()?;
+        let sign = options
+            .sign
+            .unwrap_or_else(|| config.get_bool("commit.gpgsign").unwrap_or(false));
+        // Fail on a bad signing setup before any hook has run
+        let signer = if sign {
+            Some(Signer::from_config(&config)?)
+        } else {
+            None
+        };
+
+        if !options.no_verify && !self.run_hook("pre-commit", &[])? {
+            anyhow::bail!("Commit aborted by the pre-commit hook");
+        }
+
+        let message = self.commit_message_via_hooks(message, options.no_verify)?;
+
+        // Hooks such as formatters may have staged more changes
+        let mut index = self.repo.index()?;
+        index.read(true)?;
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+
+        let head = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e.into()),
+        };
+        let (parents, author): (Vec<git2::Commit>, Signature<'static>) = if options.amend {
+            // The amended commit takes the place of HEAD and keeps its author
+            let head = head.context("Nothing to amend: the current branch has no commits yet")?;
+            let author = head.author().to_owned();
+            (head.parents().collect(), author)
+        } else {
+            if head.as_ref().map(|h| h.tree_id()) == Some(tree.id()) {
+                anyhow::bail!("No changes to commit. All changes may already be committed.");
+            }
+            (head.into_iter().collect(), self.signature("AUTHOR")?)
+        };
+
+        let committer = self.signature("COMMITTER")?;
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        let oid = match signer {
+            Some(signer) => {
+                let buffer = self
+                    .repo
+                    .commit_create_buffer(&author, &committer, &message, &tree, &parents)?;
+                let buffer = buffer
+                    .as_str()
+                    .context("Commit buffer is not valid UTF-8")?;
+                let identity = format!(
+                    "{} <{}>",
+                    committer.name().unwrap_or_default(),
+                    committer.email().unwrap_or_default()
+                );
+                let signature = signer
+                    .sign(buffer, &identity, self.repo.path())
+                    .context("Failed to sign the commit")?;
+                self.repo
+                    .commit_signed(buffer, &signature, None)
+                    .context("Failed to create commit")?
+            }
+            None => self
+                .repo
+                .commit(None, &author, &committer, &message, &tree, &parents)
+                .context("Failed to create commit")?,
+        };
+        // Neither call above moves HEAD: commit_signed never does, and
+        // commit(Some("HEAD")) refuses to replace HEAD when amending
+        let reflog = if options.amend {
+            "commit (amend)"
+        } else if parents.is_empty() {
+            "commit (initial)"
+        } else {
+            "commit"
+        };
+        self.update_head(oid, &message, reflog)?;
+
+        // Like git, a failing post-commit hook doesn't undo the commit
+        self.run_hook("post-commit", &[])?;
+        Ok(oid)
+    }
+
+    /// Points HEAD, or the branch it refers to, at a new commit, logging it
+    /// as `<action>: <subject>` like git does.
+    fn update_head(&self, oid: Oid, message: &str, action: &str) -> Result<()> {
+        let subject = message.lines().next().unwrap_or_default();
+        let log_message = format!("{}: {}", action, subject);
+
+        let head = self.repo.find_reference("HEAD")?;
+        match head.symbolic_target() {
+            Some(branch) => {
+                self.repo
+                    .reference(branch, oid, true, &log_message)
+                    .with_context(|| format!("Failed to update {}", branch))?;
+            }
+            None => self.repo.set_head_detached(oid)?,
+        }
+        Ok(())
+    }
+
+    /// Runs `prepare-commit-msg` and `commit-msg` over the message and
+    /// returns it cleaned up the way `git commit -m` does.
+    fn commit_message_via_hooks(&self, message: &str, no_verify: bool) -> Result<String> {
+        let message = git2::message_prettify(message, None)?;
+        if message.trim().is_empty() {
+            anyhow::bail!("Commit message cannot be empty");
+        }
+
+        let message_file = self.repo.path().join("COMMIT_EDITMSG");
+        std::fs::write(&message_file, &message).context("Failed to write COMMIT_EDITMSG")?;
+        let file_arg = message_file.to_string_lossy().into_owned();
+
+        // "message" tells the hook the text came from -m, as git does
+        if !self.run_hook("prepare-commit-msg", &[&file_arg, "message"])? {
+            anyhow::bail!("Commit aborted by the prepare-commit-msg hook");
+        }
+        if !no_verify && !self.run_hook("commit-msg", &[&file_arg])? {
+            anyhow::bail!("Commit aborted by the commit-msg hook");
+        }
+
+        let message = std::fs::read_to_string(&message_file)?;
+        Ok(git2::message_prettify(message, None)?)
+    }
+
+    /// Identity for `role` ("AUTHOR" or "COMMITTER"), honouring git's
+    /// environment overrides.
+    fn signature(&self, role: &str) -> Result<Signature<'static>> {
+        let default = self.repo.signature().ok();
+        let name = std::env::var(format!("GIT_{}_NAME", role))
+            .ok()
+            .or_else(|| default.as_ref().and_then(|s| s.name().map(str::to_string)));
+        let email = std::env::var(format!("GIT_{}_EMAIL", role))
+            .ok()
+            .or_else(|| default.as_ref().and_then(|s| s.email().map(str::to_string)));
+
+        match (name, email) {
+            (Some(name), Some(email)) => Ok(Signature::now(&name, &email)?),
+            _ => anyhow::bail!(
+                "Git user not configured. Please run:\n  git config --global user.email \"you@example.com\"\n  git config --global user.name \"Your Name\""
+            ),
+        }
+    }
 
-        Ok(diff_text)
+    fn workdir(&self) -> Result<&Path> {
+        self.repo
+            .workdir()
+            .context("Bare repositories are not supported")
+    }
+
+    /// Where git looks for hooks: `core.hooksPath`, or `.git/hooks`.
+    pub fn hooks_dir(&self) -> Result<PathBuf> {
+        match self.repo.config()?.get_path("core.hooksPath") {
+            Ok(path) if path.is_absolute() => Ok(path),
+            Ok(path) => Ok(self.workdir()?.join(path)),
+            Err(_) => Ok(self.repo.path().join("hooks")),
+        }
+    }
+
+    /// Runs a hook from the working tree root with inherited stdio. Returns
+    /// whether it succeeded; a missing or non-executable hook counts as success.
+    fn run_hook(&self, name: &str, args: &[&str]) -> Result<bool> {
+        let hook = self.hooks_dir()?.join(name);
+        if !is_executable(&hook) {
+            return Ok(true);
+        }
+
+        let mut command = if cfg!(windows) {
+            // Hooks are usually shell scripts, which Windows can't run directly
+            let mut command = Command::new("sh");
+            command.arg(&hook);
+            command
+        } else {
+            Command::new(&hook)
+        };
+        let status = command
+            .args(args)
+            .current_dir(self.workdir()?)
+            .status()
+            .with_context(|| format!("Failed to run the {} hook", name))?;
+        Ok(status.success())
     }
 
     pub fn get_combined_diff(&self) -> Result<String> {
@@ -172,23 +807,352 @@ impl GitRepo {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeStatus {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+    Copied,
+    /// e.g. a regular file replaced by a symlink
+    TypeChange,
+}
+
+/// One file's entry in a diff.
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub status: ChangeStatus,
+    /// `None` for added files
+    pub old_path: Option<String>,
+    /// `None` for deleted files
+    pub new_path: Option<String>,
+    /// Similarity percentage of a rename or copy
+    pub similarity: Option<u16>,
+    /// File mode after the change, or before it for deletions
+    pub mode: u32,
+    /// Old and new file mode, when they differ
+    pub mode_change: Option<(u32, u32)>,
+    pub binary: bool,
+    /// Matched `[commit] exclude` or `.gitattributes`; rendered as a summary only
+    pub excluded: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    /// The `@@ -a,b +c,d @@` line, including its newline
+    pub header: String,
+    pub lines: Vec<HunkLine>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HunkLine {
+    /// `+`, `-`, ` `, or one of git's end-of-file markers (`=`, `>`, `<`)
+    pub origin: char,
+    pub content: String,
+}
+
+impl FileChange {
+    /// The path the file has after the change, or had before a deletion.
+    pub fn path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or("unknown")
+    }
+
+    pub fn line_stats(&self) -> (usize, usize) {
+        let lines = self.hunks.iter().flat_map(|hunk| &hunk.lines);
+        lines.fold((0, 0), |(added, removed), line| match line.origin {
+            '+' => (added + 1, removed),
+            '-' => (added, removed + 1),
+            _ => (added, removed),
+        })
+    }
+
+    /// One line such as `renamed: src/a.rs -> src/b.rs (92%)`.
+    pub fn describe(&self) -> String {
+        let mut text = match self.status {
+            ChangeStatus::Added => format!("new file: {}", self.path()),
+            ChangeStatus::Deleted => format!("deleted: {}", self.path()),
+            ChangeStatus::Modified => format!("modified: {}", self.path()),
+            ChangeStatus::TypeChange => format!("typechange: {}", self.path()),
+            ChangeStatus::Renamed | ChangeStatus::Copied => format!(
+                "{}: {} -> {}",
+                if self.status == ChangeStatus::Renamed {
+                    "renamed"
+                } else {
+                    "copied"
+                },
+                self.old_path.as_deref().unwrap_or("unknown"),
+                self.path()
+            ),
+        };
+        if let Some(similarity) = self.similarity {
+            text.push_str(&format!(" ({}%)", similarity));
+        }
+        if let Some((old_mode, new_mode)) = self.mode_change {
+            text.push_str(&format!(" [mode {:o} -> {:o}]", old_mode, new_mode));
+        }
+        if self.binary {
+            text.push_str(" [binary]");
+        }
+        text
+    }
+
+    /// A copy holding only the hunks where `keep` is true. The hunks that
+    /// follow a dropped one are renumbered, since `git apply` places them by
+    /// their position in the new file.
+    pub fn with_hunks(&self, keep: &[bool]) -> FileChange {
+        let mut shift = 0i64;
+        let mut hunks = Vec::new();
+        for (hunk, &kept) in self.hunks.iter().zip(keep) {
+            let (old_lines, new_lines) =
+                hunk.lines
+                    .iter()
+                    .fold((0, 0), |(old, new), line| match line.origin {
+                        ' ' => (old + 1, new + 1),
+                        '-' => (old + 1, new),
+                        '+' => (old, new + 1),
+                        _ => (old, new),
+                    });
+            if kept {
+                hunks.push(Hunk {
+                    header: shift_new_start(&hunk.header, shift),
+                    lines: hunk.lines.clone(),
+                });
+            } else {
+                shift -= new_lines - old_lines;
+            }
+        }
+        FileChange {
+            hunks,
+            ..self.clone()
+        }
+    }
+
+    /// Git-style patch text, with extended headers for renames, copies, mode
+    /// changes and binary files.
+    pub fn render(&self) -> String {
+        let old_path = self.old_path.as_deref().unwrap_or(self.path());
+        let new_path = self.path();
+        let mut text = format!("diff --git a/{} b/{}\n", old_path, new_path);
+
+        if self.excluded {
+            // Still committed, just not worth the prompt space
+            let (added, removed) = self.line_stats();
+            text.push_str(&format!(
+                "# {}: file changed ({} lines)\n",
+                new_path,
+                added + removed
+            ));
+            return text;
+        }
+
+        match self.status {
+            ChangeStatus::Added => text.push_str(&format!("new file mode {:06o}\n", self.mode)),
+            ChangeStatus::Deleted => {
+                text.push_str(&format!("deleted file mode {:06o}\n", self.mode))
+            }
+            ChangeStatus::Renamed | ChangeStatus::Copied => {
+                let verb = if self.status == ChangeStatus::Renamed {
+                    "rename"
+                } else {
+                    "copy"
+                };
+                if let Some(similarity) = self.similarity {
+                    text.push_str(&format!("similarity index {}%\n", similarity));
+                }
+                text.push_str(&format!(
+                    "{} from {}\n{} to {}\n",
+                    verb, old_path, verb, new_path
+                ));
+            }
+            ChangeStatus::TypeChange => text.push_str("type changed\n"),
+            ChangeStatus::Modified => {}
+        }
+        if let Some((old_mode, new_mode)) = self.mode_change {
+            text.push_str(&format!(
+                "old mode {:06o}\nnew mode {:06o}\n",
+                old_mode, new_mode
+            ));
+        }
+
+        let old_name = match self.status {
+            ChangeStatus::Added => "/dev/null".to_string(),
+            _ => format!("a/{}", old_path),
+        };
+        let new_name = match self.status {
+            ChangeStatus::Deleted => "/dev/null".to_string(),
+            _ => format!("b/{}", new_path),
+        };
+
+        if self.binary {
+            text.push_str(&format!(
+                "Binary files {} and {} differ\n",
+                old_name, new_name
+            ));
+            return text;
+        }
+        if self.hunks.is_empty() {
+            return text;
+        }
+
+        text.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+        for hunk in &self.hunks {
+            text.push_str(&hunk.header);
+            for line in &hunk.lines {
+                if matches!(line.origin, '+' | '-' | ' ') {
+                    text.push(line.origin);
+                }
+                text.push_str(&line.content);
+            }
+        }
+        text
+    }
+}
+
+/// Moves the `+c` start of a `@@ -a,b +c,d @@` header by `shift` lines.
+fn shift_new_start(header: &str, shift: i64) -> String {
+    let parsed = header.split_once(" +").and_then(|(before, rest)| {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let start: i64 = rest[..digits].parse().ok()?;
+        Some(format!("{} +{}{}", before, start + shift, &rest[digits..]))
+    });
+    parsed.unwrap_or_else(|| header.to_string())
+}
+
+/// A commit in a branch range.
+#[derive(Debug, Clone)]
+pub struct CommitInfo {
+    /// Abbreviated hash
+    pub id: String,
+    pub message: String,
+}
+
 pub struct GitStatus {
     pub is_clean: bool,
-    pub modified_files: Vec<String>,
-    pub new_files: Vec<String>,
-    pub deleted_files: Vec<String>,
-    pub renamed_files: Vec<String>,
+    pub entries: Vec<StatusEntry>,
 }
 
 impl GitStatus {
     pub fn total_changes(&self) -> usize {
-        self.modified_files.len()
-            + self.new_files.len()
-            + self.deleted_files.len()
-            + self.renamed_files.len()
+        self.entries.len()
+    }
+}
+
+/// One path in `git status`, with its staged and unstaged state kept apart.
+#[derive(Debug, Clone)]
+pub struct StatusEntry {
+    pub path: String,
+    /// Where a renamed file came from
+    pub old_path: Option<String>,
+    /// Index vs HEAD
+    pub index: FileState,
+    /// Working tree vs index
+    pub worktree: FileState,
+}
+
+impl StatusEntry {
+    pub fn is_staged(&self) -> bool {
+        !matches!(
+            self.index,
+            FileState::Unmodified | FileState::Untracked | FileState::Conflicted
+        )
+    }
+
+    pub fn is_conflicted(&self) -> bool {
+        self.index == FileState::Conflicted
+    }
+
+    /// `old -> new` for renames, the path otherwise.
+    pub fn display_path(&self) -> String {
+        match &self.old_path {
+            Some(old_path) => format!("{} -> {}", old_path, self.path),
+            None => self.path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    Unmodified,
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    TypeChange,
+    Untracked,
+    Conflicted,
+}
+
+impl FileState {
+    fn index(status: git2::Status) -> Self {
+        if status.is_conflicted() {
+            FileState::Conflicted
+        } else if status.is_index_new() {
+            FileState::Added
+        } else if status.is_index_renamed() {
+            // Edited renames also carry the modified flag; git shows them as R
+            FileState::Renamed
+        } else if status.is_index_modified() {
+            FileState::Modified
+        } else if status.is_index_deleted() {
+            FileState::Deleted
+        } else if status.is_index_typechange() {
+            FileState::TypeChange
+        } else if status.is_wt_new() {
+            FileState::Untracked
+        } else {
+            FileState::Unmodified
+        }
+    }
+
+    fn worktree(status: git2::Status) -> Self {
+        if status.is_conflicted() {
+            FileState::Conflicted
+        } else if status.is_wt_new() {
+            FileState::Untracked
+        } else if status.is_wt_renamed() {
+            FileState::Renamed
+        } else if status.is_wt_modified() {
+            FileState::Modified
+        } else if status.is_wt_deleted() {
+            FileState::Deleted
+        } else if status.is_wt_typechange() {
+            FileState::TypeChange
+        } else {
+            FileState::Unmodified
+        }
+    }
+
+    pub fn code(self) -> char {
+        match self {
+            FileState::Unmodified => ' ',
+            FileState::Added => 'A',
+            FileState::Modified => 'M',
+            FileState::Deleted => 'D',
+            FileState::Renamed => 'R',
+            FileState::TypeChange => 'T',
+            FileState::Untracked => '?',
+            FileState::Conflicted => 'U',
+        }
     }
 }
 
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
 pub struct BranchInfo {
     pub name: Option<String>,
     pub is_detached: bool,
@@ -200,3 +1164,331 @@ pub struct TrackingInfo {
     pub ahead: usize,
     pub behind: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Status;
+    use tempfile::TempDir;
+
+    /// An empty repository with an identity configured, and a handle on it.
+    fn temp_repo() -> (TempDir, GitRepo) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        let repo = GitRepo::open(dir.path()).unwrap();
+        (dir, repo)
+    }
+
+    fn write(dir: &TempDir, path: &str, contents: impl AsRef<[u8]>) {
+        let path = dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    /// Stages everything and commits it straight through git2, without hooks.
+    fn commit_all(repo: &GitRepo, message: &str) -> Oid {
+        repo.stage_all().unwrap();
+        let repo = &repo.repo;
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let signature = repo.signature().unwrap();
+        let parents: Vec<git2::Commit> = repo
+            .head()
+            .ok()
+            .map(|head| head.peel_to_commit().unwrap())
+            .into_iter()
+            .collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )
+        .unwrap()
+    }
+
+    fn code(status: Status) -> String {
+        format!(
+            "{}{}",
+            FileState::index(status).code(),
+            FileState::worktree(status).code()
+        )
+    }
+
+    #[test]
+    fn short_codes_match_git() {
+        let cases = [
+            (Status::INDEX_NEW | Status::WT_MODIFIED, "AM"),
+            (Status::INDEX_RENAMED | Status::WT_MODIFIED, "RM"),
+            (
+                Status::INDEX_RENAMED | Status::INDEX_MODIFIED | Status::WT_MODIFIED,
+                "RM",
+            ),
+            (Status::INDEX_MODIFIED | Status::WT_DELETED, "MD"),
+            (Status::INDEX_TYPECHANGE, "T "),
+            (Status::WT_TYPECHANGE, " T"),
+            (Status::WT_NEW, "??"),
+            (Status::WT_RENAMED, " R"),
+            (Status::CONFLICTED | Status::INDEX_MODIFIED, "UU"),
+        ];
+        for (status, expected) in cases {
+            assert_eq!(code(status), expected, "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn dropped_hunks_shift_the_ones_after_them() {
+        let hunk = |header: &str, lines: &[(char, &str)]| Hunk {
+            header: header.to_string(),
+            lines: lines
+                .iter()
+                .map(|&(origin, content)| HunkLine {
+                    origin,
+                    content: format!("{}\n", content),
+                })
+                .collect(),
+        };
+        let change = FileChange {
+            status: ChangeStatus::Modified,
+            old_path: Some("a.txt".to_string()),
+            new_path: Some("a.txt".to_string()),
+            similarity: None,
+            mode: 0o100644,
+            mode_change: None,
+            binary: false,
+            excluded: false,
+            hunks: vec![
+                hunk("@@ -1,0 +1,2 @@\n", &[('+', "x"), ('+', "y")]),
+                hunk("@@ -9,2 +11,1 @@ fn main\n", &[(' ', "a"), ('-', "b")]),
+                hunk("@@ -20 +21 @@\n", &[('-', "c"), ('+', "d")]),
+            ],
+        };
+
+        let partial = change.with_hunks(&[false, true, true]);
+        let headers: Vec<&str> = partial.hunks.iter().map(|h| h.header.as_str()).collect();
+        assert_eq!(headers, ["@@ -9,2 +9,1 @@ fn main\n", "@@ -20 +19 @@\n"]);
+
+        let partial = change.with_hunks(&[true, false, true]);
+        assert_eq!(partial.hunks[1].header, "@@ -20 +22 @@\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mode_changes_render_old_and_new_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, repo) = temp_repo();
+        write(&dir, "run.sh", "echo hi\n");
+        commit_all(&repo, "init");
+        let script = dir.path().join("run.sh");
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let diff = repo.get_diff(false).unwrap();
+        assert_eq!(
+            diff,
+            "diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\n"
+        );
+        let changes = repo.get_changes(false).unwrap();
+        assert_eq!(
+            changes[0].describe(),
+            "modified: run.sh [mode 100644 -> 100755]"
+        );
+    }
+
+    #[test]
+    fn binary_files_render_a_summary_line() {
+        let (dir, repo) = temp_repo();
+        write(&dir, "logo.png", b"\x89PNG\0\x01");
+        commit_all(&repo, "init");
+        write(&dir, "logo.png", b"\x89PNG\0\x02");
+        write(&dir, "new.bin", b"\0\xff");
+        repo.stage_all().unwrap();
+
+        let diff = repo.get_diff(true).unwrap();
+        assert_eq!(
+            diff,
+            "diff --git a/logo.png b/logo.png\n\
+             Binary files a/logo.png and b/logo.png differ\n\
+             diff --git a/new.bin b/new.bin\n\
+             new file mode 100644\n\
+             Binary files /dev/null and b/new.bin differ\n"
+        );
+    }
+
+    #[test]
+    fn renames_render_similarity_and_both_paths() {
+        let (dir, repo) = temp_repo();
+        let lines: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        write(&dir, "old.txt", &lines);
+        commit_all(&repo, "init");
+        std::fs::remove_file(dir.path().join("old.txt")).unwrap();
+        write(&dir, "new.txt", lines.replace("line 20", "line twenty"));
+
+        // Unstaged, the move is a deletion plus an untracked file
+        let unstaged = repo.get_changes(false).unwrap();
+        assert_eq!(unstaged[0].describe(), "renamed: old.txt -> new.txt (95%)");
+
+        repo.stage_all().unwrap();
+        let diff = repo.get_diff(true).unwrap();
+        assert!(
+            diff.starts_with(
+                "diff --git a/old.txt b/new.txt\n\
+                 similarity index 95%\n\
+                 rename from old.txt\n\
+                 rename to new.txt\n\
+                 --- a/old.txt\n\
+                 +++ b/new.txt\n"
+            ),
+            "{}",
+            diff
+        );
+
+        let status = repo.get_status().unwrap();
+        assert_eq!(status.entries.len(), 1);
+        assert_eq!(status.entries[0].display_path(), "old.txt -> new.txt");
+        assert_eq!(status.entries[0].index, FileState::Renamed);
+
+        let repo = repo.with_similarity_threshold(0);
+        let paths: Vec<String> = repo
+            .get_changes(true)
+            .unwrap()
+            .iter()
+            .map(FileChange::describe)
+            .collect();
+        assert_eq!(paths, ["new file: new.txt", "deleted: old.txt"]);
+    }
+
+    fn amend() -> CommitOptions {
+        CommitOptions {
+            amend: true,
+            sign: Some(false),
+            ..CommitOptions::default()
+        }
+    }
+
+    fn last_reflog_message(repo: &GitRepo) -> String {
+        let head = repo.repo.head().unwrap();
+        let reflog = repo.repo.reflog(head.name().unwrap()).unwrap();
+        reflog.get(0).unwrap().message().unwrap().to_string()
+    }
+
+    #[test]
+    fn amending_keeps_the_author_and_parents() {
+        let (dir, repo) = temp_repo();
+        write(&dir, "a.txt", "a\n");
+        let parent = commit_all(&repo, "add a");
+        write(&dir, "b.txt", "b\n");
+        let original = commit_all(&repo, "add b");
+        repo.repo
+            .config()
+            .unwrap()
+            .set_str("user.name", "Amender")
+            .unwrap();
+        write(&dir, "c.txt", "c\n");
+        repo.stage_all().unwrap();
+
+        // The last commit's own changes plus what was staged since
+        let files: Vec<String> = repo
+            .get_amend_changes()
+            .unwrap()
+            .iter()
+            .map(|change| change.path().to_string())
+            .collect();
+        assert_eq!(files, ["b.txt", "c.txt"]);
+
+        let oid = repo.commit("add b and c", &amend()).unwrap();
+        let amended = repo.repo.find_commit(oid).unwrap();
+        let original = repo.repo.find_commit(original).unwrap();
+        assert_eq!(repo.repo.head().unwrap().target(), Some(oid));
+        assert_eq!(amended.parent_ids().collect::<Vec<_>>(), [parent]);
+        assert_eq!(amended.author().name(), Some("Test"));
+        assert_eq!(amended.author().when(), original.author().when());
+        assert_eq!(amended.committer().name(), Some("Amender"));
+        assert_eq!(amended.message(), Some("add b and c\n"));
+        assert!(amended.tree().unwrap().get_name("c.txt").is_some());
+        assert_eq!(last_reflog_message(&repo), "commit (amend): add b and c");
+    }
+
+    #[test]
+    fn amending_a_root_commit_keeps_it_parentless() {
+        let (dir, repo) = temp_repo();
+        write(&dir, "a.txt", "a\n");
+        commit_all(&repo, "init");
+
+        assert_eq!(
+            repo.get_amend_diff().unwrap().matches("diff --git").count(),
+            1
+        );
+        let oid = repo.commit("first", &amend()).unwrap();
+        let amended = repo.repo.find_commit(oid).unwrap();
+        assert_eq!(amended.parent_count(), 0);
+        assert_eq!(repo.head_message().unwrap(), "first\n");
+        assert_eq!(last_reflog_message(&repo), "commit (amend): first");
+    }
+
+    #[test]
+    fn amending_an_unborn_branch_fails() {
+        let (dir, repo) = temp_repo();
+        write(&dir, "a.txt", "a\n");
+        repo.stage_all().unwrap();
+
+        let err = repo.commit("first", &amend()).unwrap_err();
+        assert!(err.to_string().starts_with("Nothing to amend"), "{}", err);
+        let err = repo.get_amend_changes().unwrap_err();
+        assert!(err.to_string().starts_with("Nothing to amend"), "{}", err);
+        assert!(repo.repo.head().is_err());
+    }
+
+    #[test]
+    fn untracked_directories_are_listed_file_by_file() {
+        let (dir, repo) = temp_repo();
+        write(&dir, "a.txt", "a\n");
+        commit_all(&repo, "init");
+        write(&dir, "newdir/b.txt", "b\n");
+        write(&dir, "newdir/sub/c.txt", "c\n");
+
+        let status = repo.get_status().unwrap();
+        let paths: Vec<&str> = status.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["newdir/b.txt", "newdir/sub/c.txt"]);
+        let changes: Vec<String> = repo
+            .get_changes(false)
+            .unwrap()
+            .iter()
+            .map(|change| change.path().to_string())
+            .collect();
+        assert_eq!(changes, paths);
+
+        // Choosing files to stage passes these paths straight on
+        repo.stage_paths(&["newdir/sub/c.txt".to_string()]).unwrap();
+        let staged = repo.get_diff(true).unwrap();
+        assert!(staged.starts_with("diff --git a/newdir/sub/c.txt b/newdir/sub/c.txt\n"));
+        assert_eq!(staged.matches("diff --git ").count(), 1);
+    }
+
+    #[test]
+    fn a_failed_stage_only_can_be_undone() {
+        let (dir, repo) = temp_repo();
+        write(&dir, "a.txt", "a\n");
+        commit_all(&repo, "init");
+        write(&dir, "a.txt", "a2\n");
+        write(&dir, "newdir/b.txt", "b\n");
+        repo.stage_paths(&["a.txt".to_string()]).unwrap();
+        let before = repo.get_diff(true).unwrap();
+
+        let staged = repo.index_tree().unwrap();
+        // A directory isn't a path git can stage
+        let err = repo
+            .stage_only(&["newdir/b.txt".to_string(), "newdir".to_string()])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Failed to stage newdir");
+        repo.restore_index(staged).unwrap();
+        assert_eq!(repo.get_diff(true).unwrap(), before);
+    }
+}
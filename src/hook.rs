@@ -0,0 +1,184 @@
+//! The `prepare-commit-msg` hook that lets plain `git commit`, and editors
+//! that call it, use generated messages.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+const HOOK_NAME: &str = "prepare-commit-msg";
+
+/// Identifies a hook written by `install`, so it is never chained to itself.
+const MARKER: &str = "# Installed by rust-commit.";
+
+/// A hook that was already in place is moved here and run first.
+const CHAINED_NAME: &str = "prepare-commit-msg.rust-commit-chained";
+
+pub enum Installed {
+    /// No hook was there before
+    Fresh,
+    /// An existing hook was kept and runs before ours
+    Chained,
+    /// Our hook was already installed and has been rewritten
+    Updated,
+}
+
+/// Writes the hook into `hooks_dir`, calling back into `exe`. A hook that
+/// isn't ours is renamed and chained rather than overwritten.
+pub fn install(hooks_dir: &Path, exe: &Path) -> Result<Installed> {
+    std::fs::create_dir_all(hooks_dir)
+        .with_context(|| format!("Failed to create {}", hooks_dir.display()))?;
+    let hook = hooks_dir.join(HOOK_NAME);
+    let chained = hooks_dir.join(CHAINED_NAME);
+
+    let installed = if is_ours(&hook) {
+        Installed::Updated
+    } else if hook.exists() {
+        if chained.exists() {
+            anyhow::bail!(
+                "Both {} and {} exist; move one of them aside first",
+                hook.display(),
+                chained.display()
+            );
+        }
+        std::fs::rename(&hook, &chained).with_context(|| {
+            format!("Failed to move the existing hook to {}", chained.display())
+        })?;
+        Installed::Chained
+    } else {
+        Installed::Fresh
+    };
+
+    std::fs::write(&hook, script(exe))
+        .with_context(|| format!("Failed to write {}", hook.display()))?;
+    make_executable(&hook)?;
+    Ok(installed)
+}
+
+/// Removes our hook and puts back the one it chained. Returns false when
+/// there was nothing of ours to remove.
+pub fn uninstall(hooks_dir: &Path) -> Result<bool> {
+    let hook = hooks_dir.join(HOOK_NAME);
+    if !hook.exists() {
+        return Ok(false);
+    }
+    if !is_ours(&hook) {
+        anyhow::bail!(
+            "{} was not installed by rust-commit; leaving it in place",
+            hook.display()
+        );
+    }
+
+    std::fs::remove_file(&hook).with_context(|| format!("Failed to remove {}", hook.display()))?;
+    let chained = hooks_dir.join(CHAINED_NAME);
+    if chained.exists() {
+        std::fs::rename(&chained, &hook)
+            .with_context(|| format!("Failed to restore {}", hook.display()))?;
+    }
+    Ok(true)
+}
+
+/// Path of the hook git would run, for messages.
+pub fn hook_path(hooks_dir: &Path) -> PathBuf {
+    hooks_dir.join(HOOK_NAME)
+}
+
+/// Whether to fill in the message. Git passes a `source` for `-m`/`-F`,
+/// templates, merges, squashes and amends; all of those already have a
+/// message, as does a file a chained hook has written to.
+pub fn should_generate(source: Option<&str>, message: &str) -> bool {
+    source.map(str::is_empty).unwrap_or(true) && !has_content(message)
+}
+
+/// Puts `generated` above git's comment lines, keeping them for the editor.
+pub fn insert_message(existing: &str, generated: &str) -> String {
+    format!("{}\n{}", generated.trim_end(), existing)
+}
+
+fn has_content(message: &str) -> bool {
+    message
+        .lines()
+        // Everything below the scissors line of `git commit -v` is the diff
+        .take_while(|line| !line.starts_with("# ------------------------ >8"))
+        .any(|line| !line.trim().is_empty() && !line.starts_with('#'))
+}
+
+fn is_ours(hook: &Path) -> bool {
+    std::fs::read_to_string(hook)
+        .map(|script| script.contains(MARKER))
+        .unwrap_or(false)
+}
+
+fn script(exe: &Path) -> String {
+    let exe = exe.to_string_lossy();
+    // Git for Windows runs hooks with its own sh, which wants forward slashes
+    let exe = if cfg!(windows) {
+        exe.replace('\\', "/")
+    } else {
+        exe.into_owned()
+    };
+    format!(
+        r#"#!/bin/sh
+{marker} Remove with `rust-commit hook uninstall`.
+chained="$(dirname "$0")/{chained}"
+if [ -x "$chained" ]; then
+    "$chained" "$@" || exit $?
+fi
+exec {exe} hook run "$@"
+"#,
+        marker = MARKER,
+        chained = CHAINED_NAME,
+        exe = shell_quote(&exe)
+    )
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDITOR_TEMPLATE: &str = "\n# Please enter the commit message for your changes. Lines starting\n# with '#' will be ignored.\n#\n# On branch main\n";
+
+    #[test]
+    fn generates_only_for_plain_commits() {
+        assert!(should_generate(None, EDITOR_TEMPLATE));
+        assert!(should_generate(Some(""), ""));
+        for source in ["message", "template", "merge", "squash", "commit"] {
+            assert!(!should_generate(Some(source), EDITOR_TEMPLATE));
+        }
+        // A chained hook already wrote something
+        assert!(!should_generate(None, "JIRA-123: \n# comment\n"));
+        // The diff below the scissors line isn't a message
+        let verbose = format!(
+            "{}# ------------------------ >8 ------------------------\ndiff --git a/x b/x\n",
+            EDITOR_TEMPLATE
+        );
+        assert!(should_generate(None, &verbose));
+    }
+
+    #[test]
+    fn message_goes_above_the_comments() {
+        let message = insert_message(EDITOR_TEMPLATE, "feat: add hook\n\n- body\n");
+        assert!(message.starts_with("feat: add hook\n\n- body\n\n# Please enter"));
+    }
+
+    #[test]
+    fn script_quotes_the_binary_path() {
+        let script = script(Path::new("/opt/it's here/rust-commit"));
+        assert!(script.contains(MARKER));
+        assert!(script.contains(r"exec '/opt/it'\''s here/rust-commit' hook run"));
+    }
+}
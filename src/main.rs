@@ -2,18 +2,24 @@ mod ai;
 mod cli;
 mod config;
 mod git;
+mod hook;
+mod signing;
 mod ui;
 
 use anyhow::{Context, Result};
 use clap::Parser;
 use colored::*;
 use std::env;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use crate::cli::{Args, Commands};
+use crate::ai::ProviderRegistry;
+use crate::cli::{Args, Commands, HookAction};
 use crate::config::Config;
-use crate::git::GitRepo;
-use crate::ui::{CommitAction, CommitUI};
+use crate::git::{ChangeStatus, CommitInfo, CommitOptions, FileState, GitRepo};
+use crate::ui::{
+    CommitAction, CommitUI, HunkAction, Regeneration, SecretAction, SplitAction, Staging,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -41,6 +47,12 @@ async fn main() -> Result<()> {
         }
     };
 
+    // Loaded once and passed to every command
+    let config = Config::load().unwrap_or_default();
+    let repo = repo.with_similarity_threshold(config.commit.similarity_threshold);
+    // Built once here; a fork adding its own backends registers them on it
+    let registry = ProviderRegistry::with_builtin();
+
     // Handle commands
     match args.command {
         Some(Commands::Commit {
@@ -50,8 +62,78 @@ async fn main() -> Result<()> {
             auto,
             show_diff,
             debug,
+            candidates,
+            no_verify,
+            sign,
+            no_sign,
+            amend,
+        }) => {
+            let commit_options = CommitOptions {
+                no_verify,
+                // clap rejects --sign together with --no-sign
+                sign: match (sign, no_sign) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+                amend,
+            };
+            handle_commit_command(
+                repo,
+                &registry,
+                &config,
+                api_key,
+                model,
+                base_url,
+                auto,
+                show_diff,
+                debug,
+                candidates,
+                commit_options,
+            )
+            .await?;
+        }
+        Some(Commands::Split {
+            api_key,
+            model,
+            base_url,
+            auto,
+            debug,
+            no_verify,
+        }) => {
+            let commit_options = CommitOptions {
+                no_verify,
+                ..Default::default()
+            };
+            handle_split_command(
+                repo,
+                &registry,
+                &config,
+                api_key,
+                model,
+                base_url,
+                auto,
+                debug,
+                commit_options,
+            )
+            .await?;
+        }
+        Some(Commands::Pr {
+            base,
+            output,
+            body_only,
+            api_key,
+            model,
+            base_url,
+            debug,
         }) => {
-            handle_commit_command(repo, api_key, model, base_url, auto, show_diff, debug).await?;
+            handle_pr_command(
+                repo, &registry, &config, base, output, body_only, api_key, model, base_url, debug,
+            )
+            .await?;
+        }
+        Some(Commands::Hook { action }) => {
+            handle_hook_command(repo, &registry, &config, action).await?;
         }
         Some(Commands::Diff { staged }) => {
             handle_diff_command(repo, staged)?;
@@ -87,6 +169,8 @@ fn handle_init_command(local: bool, force: bool) -> Result<()> {
             println!("  export OPENAI_API_KEY=\"your-api-key\"");
             println!("  # or");
             println!("  export ANTHROPIC_API_KEY=\"your-api-key\"");
+            println!("  # or");
+            println!("  export GEMINI_API_KEY=\"your-api-key\"");
             Ok(())
         }
         Err(e) => {
@@ -110,38 +194,31 @@ fn handle_status_command(repo: GitRepo, verbose: bool) -> Result<()> {
         println!("{} ✗", "Uncommitted changes detected".yellow().bold());
         println!();
 
-        if !status.modified_files.is_empty() {
-            println!("{}:", "Modified files".yellow());
-            for file in &status.modified_files {
-                println!("  {} {}", "M".yellow(), file);
-            }
-            println!();
-        }
-
-        if !status.new_files.is_empty() {
-            println!("{}:", "New files".green());
-            for file in &status.new_files {
-                println!("  {} {}", "A".green(), file);
-            }
-            println!();
-        }
-
-        if !status.deleted_files.is_empty() {
-            println!("{}:", "Deleted files".red());
-            for file in &status.deleted_files {
-                println!("  {} {}", "D".red(), file);
-            }
-            println!();
+        // Two columns like `git status -s`: staged state, then unstaged
+        for entry in &status.entries {
+            let (index, worktree) = if entry.is_conflicted() {
+                (
+                    entry.index.code().to_string().red().bold(),
+                    entry.worktree.code().to_string().red().bold(),
+                )
+            } else if entry.index == FileState::Untracked {
+                ("?".red(), "?".red())
+            } else {
+                (
+                    entry.index.code().to_string().green(),
+                    entry.worktree.code().to_string().red(),
+                )
+            };
+            println!("  {}{} {}", index, worktree, entry.display_path());
         }
+        println!();
 
-        if !status.renamed_files.is_empty() {
-            println!("{}:", "Renamed files".blue());
-            for file in &status.renamed_files {
-                println!("  {} {}", "R".blue(), file);
-            }
-            println!();
+        let staged = status.entries.iter().filter(|e| e.is_staged()).count();
+        let conflicted = status.entries.iter().filter(|e| e.is_conflicted()).count();
+        println!("{}: {}", "Staged".bold(), staged.to_string().green());
+        if conflicted > 0 {
+            println!("{}: {}", "Conflicted".bold(), conflicted.to_string().red());
         }
-
         println!(
             "{}: {}",
             "Total uncommitted changes".bold(),
@@ -199,53 +276,203 @@ fn handle_status_command(repo: GitRepo, verbose: bool) -> Result<()> {
 }
 
 fn handle_diff_command(repo: GitRepo, staged: bool) -> Result<()> {
-    let diff = if staged {
+    let sections = if staged {
         println!("{}", "Showing staged changes:".bold().green());
-        repo.get_diff(true)?
+        vec![("Staged changes", repo.get_changes(true)?)]
     } else {
         println!("{}", "Showing all changes:".bold().green());
-        repo.get_combined_diff()?
+        vec![
+            ("Staged changes", repo.get_changes(true)?),
+            ("Unstaged changes", repo.get_changes(false)?),
+        ]
     };
 
-    if diff.is_empty() {
+    if sections.iter().all(|(_, changes)| changes.is_empty()) {
         println!("{}", "No changes to show".yellow());
-    } else {
-        println!("{}", diff);
+        return Ok(());
+    }
+
+    for (title, changes) in sections.iter().filter(|(_, changes)| !changes.is_empty()) {
+        println!("\n{}", format!("=== {} ===", title).bold());
+        for change in changes {
+            println!("\n{}", change.describe().bold());
+            for hunk in &change.hunks {
+                print!("{}", hunk.header.cyan());
+                for line in &hunk.lines {
+                    let text = format!("{}{}", line.origin, line.content);
+                    match line.origin {
+                        '+' => print!("{}", text.green()),
+                        '-' => print!("{}", text.red()),
+                        ' ' => print!("{}", text),
+                        _ => print!("{}", line.content.dimmed()),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_hook_command(
+    repo: GitRepo,
+    registry: &ProviderRegistry,
+    config: &Config,
+    action: HookAction,
+) -> Result<()> {
+    match action {
+        HookAction::Install => {
+            let hooks_dir = repo.hooks_dir()?;
+            let exe = env::current_exe().context("Failed to locate the rust-commit binary")?;
+            let path = hook::hook_path(&hooks_dir);
+            match hook::install(&hooks_dir, &exe)? {
+                hook::Installed::Fresh => {
+                    CommitUI::show_success(&format!("Installed {}", path.display()))
+                }
+                hook::Installed::Chained => CommitUI::show_success(&format!(
+                    "Installed {}; the existing hook still runs first",
+                    path.display()
+                )),
+                hook::Installed::Updated => {
+                    CommitUI::show_success(&format!("Updated {}", path.display()))
+                }
+            }
+            CommitUI::show_info("`git commit` without -m will now start with a generated message");
+        }
+        HookAction::Uninstall => {
+            if hook::uninstall(&repo.hooks_dir()?)? {
+                CommitUI::show_success("Removed the prepare-commit-msg hook");
+            } else {
+                CommitUI::show_info("No rust-commit hook is installed");
+            }
+        }
+        HookAction::Run {
+            message_file,
+            source,
+            commit: _,
+            debug,
+        } => {
+            let existing = std::fs::read_to_string(&message_file)
+                .with_context(|| format!("Failed to read {}", message_file.display()))?;
+            if !hook::should_generate(source.as_deref(), &existing) {
+                return Ok(());
+            }
+
+            // A failure here must not block the commit; git just opens the
+            // editor with an empty message as usual
+            match generate_for_hook(repo, registry, config, debug).await {
+                Ok(Some(message)) => {
+                    std::fs::write(&message_file, hook::insert_message(&existing, &message))
+                        .with_context(|| format!("Failed to write {}", message_file.display()))?;
+                }
+                Ok(None) => {}
+                Err(e) => CommitUI::show_warning(&format!(
+                    "rust-commit could not generate a message: {:#}",
+                    e
+                )),
+            }
+        }
     }
 
     Ok(())
 }
 
+/// Generates a message for the staged changes without any prompts.
+async fn generate_for_hook(
+    repo: GitRepo,
+    registry: &ProviderRegistry,
+    config: &Config,
+    debug: bool,
+) -> Result<Option<String>> {
+    let mut repo = repo.with_exclude(&config.commit.exclude)?;
+    // `git commit -a` and `git commit <paths>` stage into a temporary index
+    if let Some(index_file) = env::var_os("GIT_INDEX_FILE") {
+        repo = repo.with_index_file(Path::new(&index_file))?;
+    }
+
+    let diff = repo.get_diff(true)?;
+    if diff.is_empty() {
+        return Ok(None);
+    }
+    let Some((diff, _)) = redact_secrets(config, diff, Vec::new(), false)? else {
+        return Ok(None);
+    };
+
+    let context = ai::CommitContext {
+        branch_name: repo.get_branch_info()?.name,
+        file_count: diff
+            .lines()
+            .filter(|l| l.starts_with("diff --git "))
+            .count(),
+        added_lines: diff.lines().filter(|l| l.starts_with('+')).count(),
+        removed_lines: diff.lines().filter(|l| l.starts_with('-')).count(),
+        previous_message: None,
+    };
+    let client =
+        registry.create_chain(provider_chain(registry, config, None, None, None, false)?)?;
+    let mut options = ai::GenerationOptions::default();
+    let prompt_diff = prepare_prompt_diff(
+        client.as_ref(),
+        &diff,
+        &context,
+        config,
+        &mut options,
+        debug,
+    )
+    .await?;
+
+    CommitUI::show_info(&format!(
+        "Generating commit message with {}...",
+        client.name()
+    ));
+    let message = client
+        .generate_commit_message(&prompt_diff, &context, &options, debug)
+        .await?;
+    Ok(Some(message.format_conventional()))
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_commit_command(
     repo: GitRepo,
+    registry: &ProviderRegistry,
+    config: &Config,
     api_key: Option<String>,
     model: Option<String>,
     base_url: Option<String>,
     auto: bool,
     show_diff: bool,
     debug: bool,
+    candidates: u32,
+    commit_options: CommitOptions,
 ) -> Result<()> {
-    // Load config
-    let config = Config::load().unwrap_or_default();
+    let repo = repo.with_exclude(&config.commit.exclude)?;
 
-    // Check for changes
+    // Check for changes; amending can just reword, so a clean tree is fine
     let status = repo.get_status()?;
-    if status.is_clean {
+    if status.is_clean && !commit_options.amend {
         CommitUI::show_info("No changes to commit");
         return Ok(());
     }
 
     // Check for unstaged changes and prompt to stage
-    check_and_stage_changes()?;
+    let staged_only = check_and_stage_changes(&repo, config.commit.auto_stage)?;
 
-    // Get diff - this should now include staged changes
-    let diff = repo.get_combined_diff()?;
+    // Get diff - this should now include staged changes. When amending it
+    // is the last commit's own diff plus whatever is staged on top of it.
+    // After a partial staging, what was left out isn't described
+    let (diff, previous_message) = if commit_options.amend {
+        (repo.get_amend_diff()?, Some(repo.head_message()?))
+    } else if staged_only {
+        (repo.get_diff(true)?, None)
+    } else {
+        (repo.get_combined_diff()?, None)
+    };
 
     // Debug: Check if we're getting the staged diff correctly
     if debug {
-        println!("Debug: Combined diff length: {}", diff.len());
+        eprintln!("Debug: Combined diff length: {}", diff.len());
         if diff.len() > 100 {
-            println!("Debug: First 100 chars of diff: {}", &diff[..100]);
+            eprintln!("Debug: First 100 chars of diff: {}", &diff[..100]);
         }
     }
 
@@ -254,18 +481,24 @@ async fn handle_commit_command(
         return Ok(());
     }
 
+    // Mask secrets before anything is shown or sent, including the
+    // message being amended
+    let messages = previous_message
+        .into_iter()
+        .map(|message| ("message being amended".to_string(), message))
+        .collect();
+    let Some((diff, messages)) = redact_secrets(config, diff, messages, !auto)? else {
+        CommitUI::show_info("Commit generation cancelled");
+        return Ok(());
+    };
+    let previous_message = messages.into_iter().next();
+
     // Show diff preview if requested
     if show_diff && !CommitUI::show_diff_preview(&diff, 30)? {
         CommitUI::show_info("Commit generation cancelled");
         return Ok(());
     }
 
-    // Get API key
-    let api_key = api_key
-        .or_else(|| config.get_api_key())
-        .or_else(|| CommitUI::get_api_key(&config.ai.provider).ok())
-        .context("No API key provided")?;
-
     // Count changes for context
     let added_lines = diff.lines().filter(|l| l.starts_with('+')).count();
     let removed_lines = diff.lines().filter(|l| l.starts_with('-')).count();
@@ -273,137 +506,704 @@ async fn handle_commit_command(
     // Get branch info
     let branch_info = repo.get_branch_info()?;
 
-    // Create context
+    // Create context; the files counted are the ones in the diff, i.e.
+    // what gets committed rather than everything `git status` listed
     let context = ai::CommitContext {
         branch_name: branch_info.name,
-        file_count: status.total_changes(),
+        file_count: diff
+            .lines()
+            .filter(|l| l.starts_with("diff --git "))
+            .count(),
         added_lines,
         removed_lines,
+        previous_message,
     };
 
     // Create AI client
-    // Use model from CLI if provided, otherwise use config
-    let final_model = model.unwrap_or(config.ai.model.clone());
-    // Use base_url from CLI if provided, otherwise use config
-    let final_base_url = base_url.or(config.ai.base_url.clone());
-    let client = ai::create_client(&config.ai.provider, api_key, final_model, final_base_url)?;
+    let mut chain = provider_chain(registry, config, api_key, model, base_url, true)?;
+    let mut client = registry.create_chain(chain.clone())?;
 
-    CommitUI::show_info("Generating commit message with AI...");
+    if debug {
+        let capabilities = client.capabilities();
+        eprintln!(
+            "Debug: Provider '{}' (api key required: {}, json mode: {}, multiple choices: {})",
+            client.name(),
+            capabilities.requires_api_key,
+            capabilities.json_mode,
+            capabilities.multiple_choices
+        );
+    }
 
-    // Generate commit message
-    let commit_message = client
-        .generate_commit_message(&diff, &context, debug)
-        .await?;
+    // Regenerating reuses the diff and context computed above
+    let mut options = ai::GenerationOptions::default();
 
-    // Handle user action
-    let action = if auto {
-        CommitAction::Accept
-    } else {
-        CommitUI::confirm_commit(&commit_message)?
-    };
+    let prompt_diff = prepare_prompt_diff(
+        client.as_ref(),
+        &diff,
+        &context,
+        config,
+        &mut options,
+        debug,
+    )
+    .await?;
 
-    match action {
-        CommitAction::Accept => {
-            execute_commit(&commit_message.format_conventional())?;
-            CommitUI::show_success("Changes committed successfully!");
+    loop {
+        CommitUI::show_info(&format!(
+            "Generating commit message with {}...",
+            client.name()
+        ));
+
+        // Generate commit message
+        let commit_message = if candidates > 1 {
+            let messages = client
+                .generate_candidates(&prompt_diff, &context, &options, candidates, debug)
+                .await?;
+            let selected = if auto || messages.len() == 1 {
+                messages.into_iter().next()
+            } else {
+                CommitUI::select_candidate(&messages)?
+            };
+            match selected {
+                Some(message) => message,
+                None => {
+                    CommitUI::show_info("Commit cancelled");
+                    break;
+                }
+            }
+        } else {
+            client
+                .generate_commit_message(&prompt_diff, &context, &options, debug)
+                .await?
+        };
+
+        // Handle user action
+        let action = if auto {
+            CommitAction::Accept
+        } else {
+            CommitUI::confirm_commit(&commit_message)?
+        };
+
+        match action {
+            CommitAction::Accept => {
+                execute_commit(
+                    &repo,
+                    &commit_message.format_conventional(),
+                    &commit_options,
+                )?;
+                if commit_options.amend {
+                    CommitUI::show_success("Last commit amended successfully!");
+                } else {
+                    CommitUI::show_success("Changes committed successfully!");
+                }
+            }
+            CommitAction::Edit(edited_message) => {
+                execute_commit(&repo, &edited_message, &commit_options)?;
+                if commit_options.amend {
+                    CommitUI::show_success("Last commit amended with edited message!");
+                } else {
+                    CommitUI::show_success("Changes committed with edited message!");
+                }
+            }
+            CommitAction::Refine(instruction) => {
+                options.feedback.push(ai::Feedback {
+                    previous: commit_message,
+                    instruction,
+                });
+                continue;
+            }
+            CommitAction::Regenerate => {
+                let current_model = chain[0].1.model.clone();
+                let temperature = options.temperature.unwrap_or(ai::DEFAULT_TEMPERATURE);
+                match CommitUI::choose_regeneration(&current_model, &config.ai.models, temperature)?
+                {
+                    Regeneration::Same => {}
+                    Regeneration::Temperature(temperature) => {
+                        options.temperature = Some(temperature);
+                    }
+                    Regeneration::Model(model) => {
+                        chain[0].1.model = model;
+                        client = registry.create_chain(chain.clone())?;
+                    }
+                    Regeneration::Cancel => {
+                        CommitUI::show_info("Commit cancelled");
+                        break;
+                    }
+                }
+                continue;
+            }
+            CommitAction::Cancel => {
+                CommitUI::show_info("Commit cancelled");
+            }
         }
-        CommitAction::Edit(edited_message) => {
-            execute_commit(&edited_message)?;
-            CommitUI::show_success("Changes committed with edited message!");
+        break;
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_split_command(
+    repo: GitRepo,
+    registry: &ProviderRegistry,
+    config: &Config,
+    api_key: Option<String>,
+    model: Option<String>,
+    base_url: Option<String>,
+    auto: bool,
+    debug: bool,
+    commit_options: CommitOptions,
+) -> Result<()> {
+    let repo = repo.with_exclude(&config.commit.exclude)?;
+
+    let status = repo.get_status()?;
+    if status.is_clean {
+        CommitUI::show_info("No changes to commit");
+        return Ok(());
+    }
+    if status.entries.iter().any(|entry| entry.is_conflicted()) {
+        anyhow::bail!("Resolve the merge conflicts before splitting the changes");
+    }
+
+    // Files are named as in the diff, one per changed file. Each is staged
+    // by its current path; a rename also stages the removal of the old one
+    let changes: Vec<_> = [repo.get_changes(true)?, repo.get_changes(false)?].concat();
+    let mut files: Vec<String> = Vec::new();
+    for change in &changes {
+        if !files.iter().any(|file| file == change.path()) {
+            files.push(change.path().to_string());
         }
-        CommitAction::Regenerate => {
-            CommitUI::show_info("Please run the command again to regenerate");
+    }
+    let paths_of = |file: &String| -> Vec<String> {
+        let mut paths: Vec<String> = changes
+            .iter()
+            .filter(|change| change.path() == file)
+            .flat_map(|change| [change.new_path.clone(), change.old_path.clone()])
+            .flatten()
+            .collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    };
+
+    let diff = repo.get_combined_diff()?;
+    let Some((diff, _)) = redact_secrets(config, diff, Vec::new(), !auto)? else {
+        CommitUI::show_info("Split cancelled");
+        return Ok(());
+    };
+
+    let branch_name = repo.get_branch_info()?.name;
+    let context_for = |diff: &str, file_count: usize| ai::CommitContext {
+        branch_name: branch_name.clone(),
+        file_count,
+        added_lines: diff.lines().filter(|l| l.starts_with('+')).count(),
+        removed_lines: diff.lines().filter(|l| l.starts_with('-')).count(),
+        previous_message: None,
+    };
+
+    let client = registry.create_chain(provider_chain(
+        registry, config, api_key, model, base_url, true,
+    )?)?;
+    CommitUI::show_info(&format!("Planning commits with {}...", client.name()));
+    let mut changesets = ai::split::plan_split(
+        client.as_ref(),
+        &ai::budget::fit_diff(&diff, config.commit.max_diff_size),
+        &files,
+        &context_for(&diff, files.len()),
+        debug,
+    )
+    .await?;
+
+    loop {
+        // Changesets whose files changed get a message for their own diff
+        for changeset in changesets.iter_mut().filter(|c| c.message.is_none()) {
+            let part = ai::split::diff_for_files(&diff, &changeset.files);
+            let context = context_for(&part, changeset.files.len());
+            let mut options = ai::GenerationOptions::default();
+            let prompt_diff = prepare_prompt_diff(
+                client.as_ref(),
+                &part,
+                &context,
+                config,
+                &mut options,
+                debug,
+            )
+            .await?;
+            CommitUI::show_info(&format!(
+                "Generating a message for {}...",
+                changeset.files.join(", ")
+            ));
+            changeset.message = Some(
+                client
+                    .generate_commit_message(&prompt_diff, &context, &options, debug)
+                    .await?,
+            );
         }
-        CommitAction::Cancel => {
-            CommitUI::show_info("Commit cancelled");
+
+        CommitUI::show_split_plan(&changesets);
+        if auto {
+            break;
         }
+        match CommitUI::choose_split_action()? {
+            SplitAction::Commit => break,
+            SplitAction::Move => {
+                if let Some((file, target)) = CommitUI::choose_file_move(&changesets)? {
+                    ai::split::move_file(&mut changesets, &file, target);
+                }
+            }
+            SplitAction::Cancel => {
+                CommitUI::show_info("Split cancelled");
+                return Ok(());
+            }
+        }
+    }
+
+    // Stage every path once before committing anything, so a path that
+    // can't be staged fails the split here rather than halfway through it
+    let staged = repo.index_tree()?;
+    let all_paths: Vec<String> = files.iter().flat_map(&paths_of).collect();
+    if let Err(e) = repo.stage_only(&all_paths) {
+        repo.restore_index(staged)?;
+        return Err(e.context("Nothing was committed"));
+    }
+
+    let total = changesets.len();
+    for (index, changeset) in changesets.iter().enumerate() {
+        let message = changeset
+            .message
+            .as_ref()
+            .context("Changeset has no commit message")?;
+        let paths: Vec<String> = changeset.files.iter().flat_map(&paths_of).collect();
+        repo.stage_only(&paths)?;
+        execute_commit(&repo, &message.format_conventional(), &commit_options)
+            .with_context(|| format!("Stopped after {} of {} commits", index, total))?;
+        CommitUI::show_success(&format!("[{}/{}] {}", index + 1, total, message.header()));
     }
 
     Ok(())
 }
 
-fn check_and_stage_changes() -> Result<()> {
-    use crate::ui::CommitUI;
-    use dialoguer::{theme::ColorfulTheme, Confirm};
+#[allow(clippy::too_many_arguments)]
+async fn handle_pr_command(
+    repo: GitRepo,
+    registry: &ProviderRegistry,
+    config: &Config,
+    base: Option<String>,
+    output: Option<PathBuf>,
+    body_only: bool,
+    api_key: Option<String>,
+    model: Option<String>,
+    base_url: Option<String>,
+    debug: bool,
+) -> Result<()> {
+    let repo = repo.with_exclude(&config.commit.exclude)?;
+
+    let Some(base) = base.or_else(|| repo.default_base()) else {
+        anyhow::bail!("No main or master branch found; pass the target branch with --base");
+    };
+    let merge_base = repo.merge_base(&base)?;
+    let commits = repo.commits_since(merge_base)?;
+    let diff = repo.get_range_diff(merge_base)?;
+    if commits.is_empty() && diff.is_empty() {
+        CommitUI::show_info(&format!("No changes on this branch since {}", base));
+        return Ok(());
+    }
 
-    // Check if there are unstaged changes
-    let status_output = Command::new("git")
-        .args(["status", "--porcelain"])
-        .output()
-        .context("Failed to check git status")?;
-
-    if status_output.status.success() {
-        let status = String::from_utf8_lossy(&status_output.stdout);
-        let has_unstaged = status.lines().any(|line| {
-            line.starts_with(" M")
-                || line.starts_with("??")
-                || line.starts_with(" D")
-                || line.starts_with(" A")
-        });
-
-        if has_unstaged {
-            println!("\n{}", "Unstaged changes detected:".yellow());
-            println!("{}", "─".repeat(50));
-
-            // Show unstaged files
-            for line in status.lines() {
-                if line.starts_with(" M") {
-                    println!("  {} {}", "M".yellow(), &line[3..]);
-                } else if line.starts_with("??") {
-                    println!("  {} {}", "?".red(), &line[3..]);
-                } else if line.starts_with(" D") {
-                    println!("  {} {}", "D".red(), &line[3..]);
-                }
-            }
-            println!("{}", "─".repeat(50));
-
-            let should_stage = Confirm::with_theme(&ColorfulTheme::default())
-                .with_prompt("Do you want to stage all changes (git add .)?")
-                .default(true)
-                .interact()?;
-
-            if should_stage {
-                let add_output = Command::new("git")
-                    .args(["add", "."])
-                    .output()
-                    .context("Failed to execute git add command")?;
-
-                if !add_output.status.success() {
-                    let error = String::from_utf8_lossy(&add_output.stderr);
-                    anyhow::bail!("Failed to stage changes: {}", error.trim());
-                }
+    // The commit messages go into the prompt too
+    let messages = commits
+        .iter()
+        .map(|commit| (format!("commit {}", commit.id), commit.message.clone()))
+        .collect();
+    let Some((diff, messages)) = redact_secrets(config, diff, messages, true)? else {
+        CommitUI::show_info("Pull request description cancelled");
+        return Ok(());
+    };
+    let commits: Vec<CommitInfo> = commits
+        .into_iter()
+        .zip(messages)
+        .map(|(commit, message)| CommitInfo { message, ..commit })
+        .collect();
 
-                CommitUI::show_info("All changes staged successfully");
-            } else {
-                CommitUI::show_info("Proceeding with only currently staged changes");
+    let context = ai::CommitContext {
+        branch_name: repo.get_branch_info()?.name,
+        file_count: diff
+            .lines()
+            .filter(|l| l.starts_with("diff --git "))
+            .count(),
+        added_lines: diff.lines().filter(|l| l.starts_with('+')).count(),
+        removed_lines: diff.lines().filter(|l| l.starts_with('-')).count(),
+        previous_message: None,
+    };
+    let client = registry.create_chain(provider_chain(
+        registry, config, api_key, model, base_url, true,
+    )?)?;
+    let mut options = ai::GenerationOptions::default();
+    let prompt_diff = prepare_prompt_diff(
+        client.as_ref(),
+        &diff,
+        &context,
+        config,
+        &mut options,
+        debug,
+    )
+    .await?;
+
+    CommitUI::show_info(&format!(
+        "Describing {} commits since {} with {}...",
+        commits.len(),
+        base,
+        client.name()
+    ));
+    let pr = ai::pr::describe_branch(
+        client.as_ref(),
+        &prompt_diff,
+        &commits,
+        &base,
+        &context,
+        &options,
+        debug,
+    )
+    .await?;
+
+    let markdown = if body_only {
+        pr.body()
+    } else {
+        pr.to_markdown()
+    };
+    match output {
+        Some(path) => {
+            std::fs::write(&path, markdown)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            CommitUI::show_success(&format!("Wrote {}", path.display()));
+        }
+        None => print!("{}", markdown),
+    }
+    Ok(())
+}
+
+/// Masks secrets in `diff` and in `messages`, commit messages sent along
+/// with it as `(source, text)`, according to `on_secret`. Returns the diff
+/// and messages to send, or `None` when the user chose to abort; without
+/// `interactive`, "ask" redacts.
+fn redact_secrets(
+    config: &Config,
+    diff: String,
+    messages: Vec<(String, String)>,
+    interactive: bool,
+) -> Result<Option<(String, Vec<String>)>> {
+    let redactor = ai::redact::Redactor::new(&config.commit.secret_patterns)?;
+    let redaction = redactor.redact(&diff);
+    let mut findings = redaction.findings;
+    let masked: Vec<String> = messages
+        .iter()
+        .map(|(source, message)| {
+            let redaction = redactor.redact_message(message, source);
+            findings.extend(redaction.findings);
+            redaction.text
+        })
+        .collect();
+    let messages = messages.into_iter().map(|(_, message)| message).collect();
+    if findings.is_empty() {
+        return Ok(Some((diff, messages)));
+    }
+
+    CommitUI::show_redaction_report(&findings);
+    let action = match config.commit.on_secret.as_str() {
+        "redact" => SecretAction::Redact,
+        "continue" => SecretAction::Continue,
+        "abort" => SecretAction::Abort,
+        _ if !interactive => SecretAction::Redact,
+        _ => CommitUI::choose_secret_action()?,
+    };
+    match action {
+        SecretAction::Redact => {
+            CommitUI::show_info(&format!(
+                "Masked {} value(s) in what is sent to the AI; the commits themselves are unchanged",
+                findings.len()
+            ));
+            Ok(Some((redaction.text, masked)))
+        }
+        SecretAction::Continue => Ok(Some((diff, messages))),
+        SecretAction::Abort => Ok(None),
+    }
+}
+
+/// The configured provider with CLI overrides applied, followed by the
+/// fallbacks. Without `prompt_for_key` there is no password prompt; a
+/// provider without an API key is skipped, and is an error only when no
+/// fallback is left.
+fn provider_chain(
+    registry: &ProviderRegistry,
+    config: &Config,
+    api_key: Option<String>,
+    model: Option<String>,
+    base_url: Option<String>,
+    prompt_for_key: bool,
+) -> Result<Vec<(String, ai::ProviderConfig)>> {
+    let retry = ai::retry::RetryPolicy::new(
+        config.ai.retry_max_attempts,
+        Duration::from_secs(config.ai.retry_deadline_secs),
+    );
+    let fallbacks = fallback_chain(registry, config, retry);
+
+    // Local providers such as Ollama don't need a key
+    let api_key = if registry.requires_api_key(&config.ai.provider) {
+        let api_key = api_key.or_else(|| config.get_api_key());
+        let api_key = match api_key {
+            Some(key) => Some(key),
+            None if prompt_for_key => CommitUI::get_api_key(&config.ai.provider).ok(),
+            None => None,
+        };
+        if api_key.is_none() {
+            // Like a keyless fallback, the provider is skipped if others can answer
+            if fallbacks.is_empty() {
+                anyhow::bail!("No API key provided");
             }
+            CommitUI::show_warning(&format!(
+                "Skipping provider '{}': no API key found",
+                config.ai.provider
+            ));
+            return Ok(fallbacks);
         }
+        api_key
+    } else {
+        // Only a key given for this provider; the api_key_env fallback would
+        // send e.g. an OpenAI key to a local server
+        api_key.or_else(|| config.ai.api_key.clone())
+    };
+
+    let mut chain = vec![(
+        config.ai.provider.clone(),
+        ai::ProviderConfig {
+            api_key,
+            model: model.unwrap_or(config.ai.model.clone()),
+            base_url: base_url.or(config.ai.base_url.clone()),
+            deployment: config.ai.deployment.clone(),
+            api_version: config.ai.api_version.clone(),
+            auth_header: config.ai.auth_header.clone(),
+            retry,
+        },
+    )];
+    chain.extend(fallbacks);
+    Ok(chain)
+}
+
+/// The diff as it goes into the prompt. Past `summarize_threshold` the parts
+/// are summarised into `options.summaries` and only an overview of the files
+/// is returned; otherwise the diff is fitted to `max_diff_size`.
+async fn prepare_prompt_diff(
+    client: &dyn ai::CommitMessageProvider,
+    diff: &str,
+    context: &ai::CommitContext,
+    config: &Config,
+    options: &mut ai::GenerationOptions,
+    debug: bool,
+) -> Result<String> {
+    let summarize_threshold = config.commit.summarize_threshold;
+    if summarize_threshold > 0 && diff.len() > summarize_threshold {
+        // Too large even for truncation: summarise the parts, then send an
+        // overview of the files along with the summaries
+        CommitUI::show_info(&format!(
+            "Diff is large ({} chars), summarising it in parts with {}...",
+            diff.len(),
+            client.name()
+        ));
+        options.summaries = ai::summarize::summarize_diff(
+            client,
+            diff,
+            context,
+            config.commit.max_diff_size,
+            config.commit.summarize_concurrency,
+            debug,
+        )
+        .await?;
+        if debug {
+            eprintln!(
+                "Debug: Summarised diff in {} parts",
+                options.summaries.len()
+            );
+        }
+        return Ok(ai::budget::fit_diff(diff, 0));
     }
 
-    Ok(())
+    // Keep every file visible to the model while staying within max_diff_size
+    let fitted = ai::budget::fit_diff(diff, config.commit.max_diff_size);
+    if debug && fitted.len() < diff.len() {
+        eprintln!(
+            "Debug: Diff reduced from {} to {} chars (max_diff_size = {})",
+            diff.len(),
+            fitted.len(),
+            config.commit.max_diff_size
+        );
+    }
+    Ok(fitted)
 }
 
-fn execute_commit(message: &str) -> Result<()> {
-    // Execute git commit
-    let commit_output = Command::new("git")
-        .args(["commit", "-m", message])
-        .output()
-        .context("Failed to execute git commit command")?;
-
-    if !commit_output.status.success() {
-        let error = String::from_utf8_lossy(&commit_output.stderr);
-
-        // Check for common git errors and provide helpful messages
-        let error_msg = if error.contains("nothing to commit") {
-            "No changes to commit. All changes may already be committed."
-        } else if error.contains("Please tell me who you are") {
-            "Git user not configured. Please run:\n  git config --global user.email \"you@example.com\"\n  git config --global user.name \"Your Name\""
-        } else {
-            error.trim()
+/// Fallback providers from `[[ai.fallback]]`. Entries that need an API key
+/// but have none are skipped rather than prompting for one.
+fn fallback_chain(
+    registry: &ProviderRegistry,
+    config: &Config,
+    retry: ai::retry::RetryPolicy,
+) -> Vec<(String, ai::ProviderConfig)> {
+    config
+        .ai
+        .fallback
+        .iter()
+        .filter_map(|entry| {
+            let api_key = entry.get_api_key();
+            if api_key.is_none() && registry.requires_api_key(&entry.provider) {
+                CommitUI::show_warning(&format!(
+                    "Skipping fallback provider '{}': no API key found",
+                    entry.provider
+                ));
+                return None;
+            }
+
+            Some((
+                entry.provider.clone(),
+                ai::ProviderConfig {
+                    api_key,
+                    model: entry.model.clone(),
+                    base_url: entry.base_url.clone(),
+                    deployment: entry.deployment.clone(),
+                    api_version: entry.api_version.clone(),
+                    auth_header: entry.auth_header.clone(),
+                    retry,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Offers to stage the unstaged changes: all of them, chosen files or
+/// chosen hunks. Returns true when unstaged changes were left out.
+fn check_and_stage_changes(repo: &GitRepo, auto_stage: bool) -> Result<bool> {
+    // Check if there are unstaged changes
+    let status = repo.get_status()?;
+    let unstaged: Vec<_> = status
+        .entries
+        .iter()
+        .filter(|entry| entry.worktree != FileState::Unmodified && !entry.is_conflicted())
+        .collect();
+
+    if unstaged.is_empty() {
+        return Ok(false);
+    }
+
+    println!("\n{}", "Unstaged changes detected:".yellow());
+    println!("{}", "─".repeat(50));
+
+    // Show unstaged files
+    for entry in &unstaged {
+        let code = entry.worktree.code().to_string();
+        let code = match entry.worktree {
+            FileState::Modified | FileState::TypeChange => code.yellow(),
+            _ => code.red(),
         };
+        println!("  {} {}", code, entry.display_path());
+    }
+    println!("{}", "─".repeat(50));
+
+    let staging = if auto_stage {
+        Staging::All
+    } else {
+        CommitUI::choose_staging()?
+    };
+
+    match staging {
+        Staging::All => {
+            repo.stage_all()?;
+            CommitUI::show_info("All changes staged successfully");
+            Ok(false)
+        }
+        Staging::Files => {
+            let chosen = CommitUI::choose_files(&unstaged)?;
+            // A rename not yet staged also stages the removal of the old path
+            let paths: Vec<String> = chosen
+                .iter()
+                .flat_map(|&i| {
+                    std::iter::once(unstaged[i].path.clone()).chain(unstaged[i].old_path.clone())
+                })
+                .collect();
+            repo.stage_paths(&paths)?;
+            CommitUI::show_info(&format!(
+                "Staged {} of {} files",
+                chosen.len(),
+                unstaged.len()
+            ));
+            Ok(chosen.len() < unstaged.len())
+        }
+        Staging::Hunks => pick_hunks(repo),
+        Staging::Staged => {
+            CommitUI::show_info("Proceeding with only currently staged changes");
+            Ok(true)
+        }
+    }
+}
+
+/// Walks the unstaged hunks like `git add -p` and stages the chosen ones.
+/// Returns true when anything was skipped.
+fn pick_hunks(repo: &GitRepo) -> Result<bool> {
+    let changes = repo.get_unstaged_hunks()?;
+    let mut patch = String::new();
+    let mut whole_files = Vec::new();
+    let mut skipped = false;
+    let mut done = false;
+
+    for change in &changes {
+        if done {
+            skipped = true;
+            continue;
+        }
+
+        // Binary files, mode or type changes and empty files: all or nothing
+        if change.binary || change.hunks.is_empty() || change.status == ChangeStatus::TypeChange {
+            if CommitUI::confirm_whole_file(change)? {
+                whole_files.extend(change.new_path.clone().or(change.old_path.clone()));
+            } else {
+                skipped = true;
+            }
+            continue;
+        }
 
-        anyhow::bail!("Git commit failed: {}", error_msg);
+        let mut keep = vec![false; change.hunks.len()];
+        let mut rest = None;
+        for (index, kept) in keep.iter_mut().enumerate() {
+            if let Some(stage_rest) = rest {
+                *kept = stage_rest;
+                continue;
+            }
+            CommitUI::show_hunk(change, index);
+            match CommitUI::choose_hunk_action()? {
+                HunkAction::Stage => *kept = true,
+                HunkAction::Skip => {}
+                HunkAction::StageRest => {
+                    *kept = true;
+                    rest = Some(true);
+                }
+                HunkAction::SkipRest => rest = Some(false),
+                HunkAction::Done => {
+                    done = true;
+                    break;
+                }
+            }
+        }
+
+        skipped |= keep.contains(&false);
+        if keep.contains(&true) {
+            patch.push_str(&change.with_hunks(&keep).render());
+        }
     }
 
+    if !patch.is_empty() {
+        repo.stage_patch(&patch)?;
+    }
+    repo.stage_paths(&whole_files)?;
+    CommitUI::show_info("Selected hunks staged successfully");
+    Ok(skipped)
+}
+
+fn execute_commit(repo: &GitRepo, message: &str, options: &CommitOptions) -> Result<()> {
+    repo.commit(message, options).context("Git commit failed")?;
     Ok(())
 }
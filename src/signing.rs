@@ -0,0 +1,257 @@
+//! Commit signing with the signer configured for git: `gpg`, `gpgsm` or
+//! `ssh-keygen`, chosen by `gpg.format`.
+
+use anyhow::{Context, Result};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningFormat {
+    OpenPgp,
+    X509,
+    Ssh,
+}
+
+impl SigningFormat {
+    fn parse(format: &str) -> Result<Self> {
+        match format {
+            "openpgp" => Ok(Self::OpenPgp),
+            "x509" => Ok(Self::X509),
+            "ssh" => Ok(Self::Ssh),
+            other => anyhow::bail!(
+                "Unsupported gpg.format '{}'. Expected openpgp, x509 or ssh",
+                other
+            ),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::OpenPgp => "openpgp",
+            Self::X509 => "x509",
+            Self::Ssh => "ssh",
+        }
+    }
+
+    fn default_program(self) -> &'static str {
+        match self {
+            Self::OpenPgp => "gpg",
+            Self::X509 => "gpgsm",
+            Self::Ssh => "ssh-keygen",
+        }
+    }
+}
+
+/// How `user.signingkey` names an SSH key.
+#[derive(Debug, PartialEq, Eq)]
+enum SshKey {
+    /// A key file; for a public key the private half must be in ssh-agent
+    File(PathBuf),
+    /// A public key given inline, which is signed with through ssh-agent
+    Literal(String),
+}
+
+impl SshKey {
+    fn parse(value: &str) -> Self {
+        // Like git, "key::" marks a literal key and a bare "ssh-..." line is
+        // accepted for compatibility
+        if let Some(key) = value.strip_prefix("key::") {
+            return Self::Literal(key.trim().to_string());
+        }
+        if value.starts_with("ssh-") || value.starts_with("ecdsa-") || value.starts_with("sk-") {
+            return Self::Literal(value.trim().to_string());
+        }
+        Self::File(expand_home(value))
+    }
+}
+
+pub struct Signer {
+    format: SigningFormat,
+    program: String,
+    /// `user.signingkey`; gpg falls back to the committer identity without it
+    key: Option<String>,
+}
+
+impl Signer {
+    /// Reads `gpg.format`, `gpg.<format>.program` (or `gpg.program` for
+    /// openpgp) and `user.signingkey` from git config.
+    pub fn from_config(config: &git2::Config) -> Result<Self> {
+        let format = match config.get_string("gpg.format") {
+            Ok(format) => SigningFormat::parse(&format)?,
+            Err(_) => SigningFormat::OpenPgp,
+        };
+        let program = config
+            .get_string(&format!("gpg.{}.program", format.name()))
+            .ok()
+            .or_else(|| match format {
+                SigningFormat::OpenPgp => config.get_string("gpg.program").ok(),
+                _ => None,
+            })
+            .unwrap_or_else(|| format.default_program().to_string());
+        let key = config
+            .get_string("user.signingkey")
+            .ok()
+            .filter(|key| !key.trim().is_empty());
+
+        Ok(Self {
+            format,
+            program,
+            key,
+        })
+    }
+
+    /// Signs a commit buffer, returning the armored signature for its
+    /// `gpgsig` header. `committer` is the "Name <email>" used to pick a gpg
+    /// key when `user.signingkey` isn't set; `scratch` is a directory for the
+    /// files `ssh-keygen` works on.
+    pub fn sign(&self, buffer: &str, committer: &str, scratch: &Path) -> Result<String> {
+        match self.format {
+            SigningFormat::OpenPgp | SigningFormat::X509 => self.sign_with_gpg(buffer, committer),
+            SigningFormat::Ssh => self.sign_with_ssh(buffer, scratch),
+        }
+    }
+
+    fn sign_with_gpg(&self, buffer: &str, committer: &str) -> Result<String> {
+        let key = self.key.as_deref().unwrap_or(committer);
+        let mut child = Command::new(&self.program)
+            .args(["--status-fd=2", "-bsau", key])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| self.spawn_error(e))?;
+        child
+            .stdin
+            .take()
+            .context("Failed to open the signer's stdin")?
+            .write_all(buffer.as_bytes())?;
+        let output = child.wait_with_output()?;
+
+        let status = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() || !status.contains("[GNUPG:] SIG_CREATED ") {
+            anyhow::bail!(
+                "{} failed to sign the commit with key '{}': {}",
+                self.program,
+                key,
+                failure_reason(&status)
+            );
+        }
+        String::from_utf8(output.stdout).context("The signature is not valid UTF-8")
+    }
+
+    fn sign_with_ssh(&self, buffer: &str, scratch: &Path) -> Result<String> {
+        let Some(key) = &self.key else {
+            anyhow::bail!(
+                "SSH signing needs a key. Set it with:\n  git config user.signingkey ~/.ssh/id_ed25519.pub"
+            );
+        };
+
+        let buffer_file = scratch.join("RUST_COMMIT_SIGN_BUFFER");
+        let signature_file = scratch.join("RUST_COMMIT_SIGN_BUFFER.sig");
+        let literal_key_file = scratch.join("RUST_COMMIT_SIGN_KEY");
+        let result = (|| {
+            std::fs::write(&buffer_file, buffer).context("Failed to write the buffer to sign")?;
+            let mut command = Command::new(&self.program);
+            command.args(["-Y", "sign", "-n", "git", "-f"]);
+            match SshKey::parse(key) {
+                SshKey::File(path) => {
+                    command.arg(path);
+                }
+                SshKey::Literal(public_key) => {
+                    std::fs::write(&literal_key_file, public_key + "\n")?;
+                    // -U: the private key is only available through ssh-agent
+                    command.arg(&literal_key_file).arg("-U");
+                }
+            }
+            let output = command
+                .arg(&buffer_file)
+                .stdin(Stdio::null())
+                .output()
+                .map_err(|e| self.spawn_error(e))?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "{} failed to sign the commit with key '{}': {}",
+                    self.program,
+                    key,
+                    failure_reason(&String::from_utf8_lossy(&output.stderr))
+                );
+            }
+            std::fs::read_to_string(&signature_file).context("Failed to read the SSH signature")
+        })();
+
+        for file in [&buffer_file, &signature_file, &literal_key_file] {
+            let _ = std::fs::remove_file(file);
+        }
+        result
+    }
+
+    fn spawn_error(&self, error: std::io::Error) -> anyhow::Error {
+        if error.kind() == ErrorKind::NotFound {
+            anyhow::anyhow!(
+                "Commit signing is enabled but '{}' was not found. Install it, point gpg.{}.program at it, or commit with --no-sign",
+                self.program,
+                self.format.name()
+            )
+        } else {
+            anyhow::Error::new(error).context(format!("Failed to run {}", self.program))
+        }
+    }
+}
+
+/// The signer's own error lines, without gpg's machine-readable status output.
+fn failure_reason(stderr: &str) -> String {
+    let reason = stderr
+        .lines()
+        .filter(|line| !line.starts_with("[GNUPG:]"))
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("; ");
+    if reason.is_empty() {
+        "no signature was produced".to_string()
+    } else {
+        reason
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()
+            .map(|home| home.join(rest))
+            .unwrap_or_else(|| PathBuf::from(path)),
+        None => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssh_keys_are_literal_or_files() {
+        assert_eq!(
+            SshKey::parse("key::ssh-ed25519 AAAAC3Nza me@host"),
+            SshKey::Literal("ssh-ed25519 AAAAC3Nza me@host".to_string())
+        );
+        assert_eq!(
+            SshKey::parse("ssh-ed25519 AAAAC3Nza"),
+            SshKey::Literal("ssh-ed25519 AAAAC3Nza".to_string())
+        );
+        assert_eq!(
+            SshKey::parse("/keys/id_ed25519.pub"),
+            SshKey::File(PathBuf::from("/keys/id_ed25519.pub"))
+        );
+        assert!(matches!(
+            SshKey::parse("~/.ssh/id_ed25519"),
+            SshKey::File(path) if path.ends_with(".ssh/id_ed25519")
+        ));
+    }
+
+    #[test]
+    fn failure_reason_drops_status_lines() {
+        let stderr = "[GNUPG:] KEY_CONSIDERED ABC 2\ngpg: signing failed: No secret key\n[GNUPG:] FAILURE sign 17\n";
+        assert_eq!(failure_reason(stderr), "gpg: signing failed: No secret key");
+        assert_eq!(failure_reason(""), "no signature was produced");
+    }
+}
@@ -1,13 +1,25 @@
+use crate::ai::redact::Finding;
+use crate::ai::split::Changeset;
 use crate::ai::CommitMessage;
+use crate::git::{FileChange, StatusEntry};
 use anyhow::Result;
 use colored::*;
-use dialoguer::{theme::ColorfulTheme, Confirm, Editor, Select};
+use dialoguer::{theme::ColorfulTheme, Confirm, Editor, Input, MultiSelect, Select};
+use reqwest::StatusCode;
+use std::time::Duration;
 
 pub struct CommitUI;
 
 impl CommitUI {
     pub fn confirm_commit(message: &CommitMessage) -> Result<CommitAction> {
-        println!("\n{}", "Generated Commit Message:".bold().green());
+        match &message.provider {
+            Some(provider) => println!(
+                "\n{} {}",
+                "Generated Commit Message:".bold().green(),
+                format!("(by {})", provider).dimmed()
+            ),
+            None => println!("\n{}", "Generated Commit Message:".bold().green()),
+        }
         println!("{}", "─".repeat(50));
 
         // Display formatted message
@@ -18,7 +30,8 @@ impl CommitUI {
         let options = vec![
             "Accept and commit",
             "Edit message",
-            "Regenerate with different AI",
+            "Refine with feedback",
+            "Regenerate",
             "Cancel",
         ];
 
@@ -42,11 +55,345 @@ impl CommitUI {
                     None => Ok(CommitAction::Cancel),
                 }
             }
-            2 => Ok(CommitAction::Regenerate),
+            2 => {
+                let instruction: String = Input::with_theme(&ColorfulTheme::default())
+                    .with_prompt("What should change? (e.g. \"make it shorter\", \"scope is api\")")
+                    .interact_text()?;
+
+                if instruction.trim().is_empty() {
+                    Ok(CommitAction::Regenerate)
+                } else {
+                    Ok(CommitAction::Refine(instruction.trim().to_string()))
+                }
+            }
+            3 => Ok(CommitAction::Regenerate),
             _ => Ok(CommitAction::Cancel),
         }
     }
 
+    /// Lets the user pick one of several generated messages, or merge them.
+    /// Returns `None` when the user cancels.
+    pub fn select_candidate(candidates: &[CommitMessage]) -> Result<Option<CommitMessage>> {
+        println!(
+            "\n{}",
+            format!("Generated {} candidates:", candidates.len())
+                .bold()
+                .green()
+        );
+
+        let mut options: Vec<String> = candidates
+            .iter()
+            .map(|message| format!("{}  {}", message.header(), message.description_en.dimmed()))
+            .collect();
+        options.push("Merge bodies of all candidates".to_string());
+        options.push("Cancel".to_string());
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Which message would you like to use?")
+            .items(&options)
+            .default(0)
+            .interact()?;
+
+        if let Some(message) = candidates.get(selection) {
+            return Ok(Some(message.clone()));
+        }
+        if selection == candidates.len() {
+            return Ok(CommitMessage::merge(candidates));
+        }
+        Ok(None)
+    }
+
+    /// Asks how to regenerate: same settings, a hotter sample or another model.
+    pub fn choose_regeneration(
+        current_model: &str,
+        models: &[String],
+        temperature: f32,
+    ) -> Result<Regeneration> {
+        let other_models: Vec<&String> = models
+            .iter()
+            .filter(|model| model.as_str() != current_model)
+            .collect();
+        let next_temperature = (temperature + 0.2).min(1.0);
+
+        let mut options = vec![format!("Same settings ({})", current_model)];
+        if next_temperature > temperature {
+            options.push(format!(
+                "Higher temperature ({:.1} → {:.1})",
+                temperature, next_temperature
+            ));
+        }
+        options.extend(other_models.iter().map(|model| format!("Use {}", model)));
+        options.push("Enter another model...".to_string());
+        options.push("Cancel".to_string());
+
+        loop {
+            let selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("How would you like to regenerate?")
+                .items(&options)
+                .default(0)
+                .interact()?;
+
+            let mut index = selection;
+            if index == 0 {
+                return Ok(Regeneration::Same);
+            }
+            if next_temperature > temperature {
+                if index == 1 {
+                    return Ok(Regeneration::Temperature(next_temperature));
+                }
+                index -= 1;
+            }
+            if let Some(model) = other_models.get(index - 1) {
+                return Ok(Regeneration::Model(model.to_string()));
+            }
+            if index != other_models.len() + 1 {
+                return Ok(Regeneration::Cancel);
+            }
+
+            let model: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Model name (empty to go back)")
+                .allow_empty(true)
+                .interact_text()?;
+            if !model.trim().is_empty() {
+                return Ok(Regeneration::Model(model.trim().to_string()));
+            }
+            // A blank name returns to the menu rather than cancelling
+        }
+    }
+
+    /// Lists masked secrets by file and detector, showing only a short prefix
+    /// of each value.
+    pub fn show_redaction_report(findings: &[Finding]) {
+        eprintln!(
+            "\n{}",
+            format!("Possible secrets found ({}):", findings.len())
+                .bold()
+                .yellow()
+        );
+        eprintln!("{}", "─".repeat(50));
+
+        let mut groups: Vec<(&str, &str, Vec<&str>)> = Vec::new();
+        for finding in findings {
+            let file = finding.file.as_deref().unwrap_or("unknown");
+            match groups
+                .iter_mut()
+                .find(|(f, detector, _)| *f == file && *detector == finding.detector)
+            {
+                Some((_, _, previews)) => previews.push(&finding.preview),
+                None => groups.push((file, &finding.detector, vec![&finding.preview])),
+            }
+        }
+        for (file, detector, previews) in groups {
+            eprintln!(
+                "  {} {} {}",
+                file.bold(),
+                detector.yellow(),
+                previews.join(", ").dimmed()
+            );
+        }
+
+        eprintln!("{}", "─".repeat(50));
+    }
+
+    pub fn choose_secret_action() -> Result<SecretAction> {
+        let options = vec![
+            "Redact them and continue",
+            "Send the diff unchanged",
+            "Abort",
+        ];
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("What would you like to do?")
+            .items(&options)
+            .default(0)
+            .interact()?;
+
+        match selection {
+            0 => Ok(SecretAction::Redact),
+            1 => Ok(SecretAction::Continue),
+            _ => Ok(SecretAction::Abort),
+        }
+    }
+
+    /// Prints the planned commits in order with their files.
+    pub fn show_split_plan(changesets: &[Changeset]) {
+        println!(
+            "\n{}",
+            format!("Planned {} commits:", changesets.len())
+                .bold()
+                .green()
+        );
+        println!("{}", "─".repeat(50));
+        for (index, changeset) in changesets.iter().enumerate() {
+            let header = match &changeset.message {
+                Some(message) => message.header().cyan().bold(),
+                None => "(message pending)".dimmed(),
+            };
+            println!("{} {}", format!("{}.", index + 1).bold(), header);
+            for file in &changeset.files {
+                println!("   {}", file);
+            }
+        }
+        println!("{}", "─".repeat(50));
+    }
+
+    pub fn choose_split_action() -> Result<SplitAction> {
+        let options = vec![
+            "Create these commits",
+            "Move a file to another commit",
+            "Cancel",
+        ];
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("What would you like to do?")
+            .items(&options)
+            .default(0)
+            .interact()?;
+
+        match selection {
+            0 => Ok(SplitAction::Commit),
+            1 => Ok(SplitAction::Move),
+            _ => Ok(SplitAction::Cancel),
+        }
+    }
+
+    /// Asks for a file and the commit it should move to. The target index
+    /// equals `changesets.len()` for a new commit at the end.
+    pub fn choose_file_move(changesets: &[Changeset]) -> Result<Option<(String, usize)>> {
+        let files: Vec<(usize, &String)> = changesets
+            .iter()
+            .enumerate()
+            .flat_map(|(index, changeset)| changeset.files.iter().map(move |file| (index, file)))
+            .collect();
+        let mut options: Vec<String> = files
+            .iter()
+            .map(|(index, file)| {
+                format!("{}  {}", file, format!("(commit {})", index + 1).dimmed())
+            })
+            .collect();
+        options.push("Back".to_string());
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Which file?")
+            .items(&options)
+            .default(0)
+            .interact()?;
+        let Some((source, file)) = files.get(selection) else {
+            return Ok(None);
+        };
+
+        let mut targets: Vec<String> = changesets
+            .iter()
+            .enumerate()
+            .map(|(index, changeset)| {
+                let header = changeset
+                    .message
+                    .as_ref()
+                    .map(|message| message.header())
+                    .unwrap_or_else(|| "(message pending)".to_string());
+                format!("{}. {}", index + 1, header)
+            })
+            .collect();
+        targets.push("A new commit at the end".to_string());
+
+        let target = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Move {} to", file))
+            .items(&targets)
+            .default(*source)
+            .interact()?;
+        Ok(Some((file.to_string(), target)))
+    }
+
+    pub fn choose_staging() -> Result<Staging> {
+        let options = vec![
+            "Stage all changes (git add -A)",
+            "Choose files to stage",
+            "Choose hunks to stage (like git add -p)",
+            "Proceed with only the staged changes",
+        ];
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("How would you like to stage?")
+            .items(&options)
+            .default(0)
+            .interact()?;
+
+        match selection {
+            0 => Ok(Staging::All),
+            1 => Ok(Staging::Files),
+            2 => Ok(Staging::Hunks),
+            _ => Ok(Staging::Staged),
+        }
+    }
+
+    /// Returns the indices of the entries to stage.
+    pub fn choose_files(entries: &[&StatusEntry]) -> Result<Vec<usize>> {
+        let options: Vec<String> = entries
+            .iter()
+            .map(|entry| format!("{} {}", entry.worktree.code(), entry.display_path()))
+            .collect();
+
+        MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt("Select files to stage (space to toggle, enter to confirm)")
+            .items(&options)
+            .interact()
+            .map_err(Into::into)
+    }
+
+    /// Prints hunk `index` of `change` with its file header.
+    pub fn show_hunk(change: &FileChange, index: usize) {
+        let hunk = &change.hunks[index];
+        println!(
+            "\n{} {}",
+            change.describe().bold(),
+            format!("(hunk {}/{})", index + 1, change.hunks.len()).dimmed()
+        );
+        print!("{}", hunk.header.cyan());
+        for line in &hunk.lines {
+            let text = format!("{}{}", line.origin, line.content.trim_end_matches('\n'));
+            match line.origin {
+                '+' => println!("{}", text.green()),
+                '-' => println!("{}", text.red()),
+                ' ' => println!("{}", text),
+                // End-of-file markers carry their own text
+                _ => println!("{}", line.content.trim().dimmed()),
+            }
+        }
+    }
+
+    pub fn choose_hunk_action() -> Result<HunkAction> {
+        let options = vec![
+            "Stage this hunk",
+            "Skip this hunk",
+            "Stage this and the rest of the file",
+            "Skip the rest of the file",
+            "Done, skip everything else",
+        ];
+
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Stage this hunk?")
+            .items(&options)
+            .default(0)
+            .interact()?;
+
+        match selection {
+            0 => Ok(HunkAction::Stage),
+            1 => Ok(HunkAction::Skip),
+            2 => Ok(HunkAction::StageRest),
+            3 => Ok(HunkAction::SkipRest),
+            _ => Ok(HunkAction::Done),
+        }
+    }
+
+    /// For changes without hunks to pick from, such as binary files.
+    pub fn confirm_whole_file(change: &FileChange) -> Result<bool> {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Stage {}?", change.describe()))
+            .default(false)
+            .interact()
+            .map_err(Into::into)
+    }
+
     pub fn show_diff_preview(diff: &str, max_lines: usize) -> Result<bool> {
         let lines: Vec<&str> = diff.lines().collect();
         let total_lines = lines.len();
@@ -83,7 +430,8 @@ impl CommitUI {
     pub fn get_api_key(provider: &str) -> Result<String> {
         use dialoguer::Password;
 
-        println!("\n{}", format!("{} API key not found!", provider).yellow());
+        // Like the prompt itself, on stderr so piped output stays clean
+        eprintln!("\n{}", format!("{} API key not found!", provider).yellow());
 
         let api_key = Password::new()
             .with_prompt(format!("Please enter your {} API key", provider))
@@ -96,18 +444,76 @@ impl CommitUI {
         Ok(api_key.trim().to_string())
     }
 
+    // Status messages go to stderr, so `rust-commit pr` output can be piped
     pub fn show_success(message: &str) {
-        println!("{} {}", "✓".green().bold(), message.green());
+        eprintln!("{} {}", "✓".green().bold(), message.green());
     }
 
     pub fn show_info(message: &str) {
-        println!("{} {}", "ℹ".blue(), message);
+        eprintln!("{} {}", "ℹ".blue(), message);
+    }
+
+    pub fn show_warning(message: &str) {
+        eprintln!("{} {}", "⚠".yellow(), message.yellow());
+    }
+
+    pub fn show_retry(
+        provider: &str,
+        status: StatusCode,
+        attempt: u32,
+        max_attempts: u32,
+        delay: Duration,
+    ) {
+        eprintln!(
+            "{} {} returned {}, retrying in {:.1}s (attempt {}/{})",
+            "↻".yellow(),
+            provider,
+            status,
+            delay.as_secs_f64(),
+            attempt + 1,
+            max_attempts
+        );
     }
 }
 
 pub enum CommitAction {
     Accept,
     Edit(String),
+    Refine(String),
     Regenerate,
     Cancel,
 }
+
+pub enum Regeneration {
+    Same,
+    Temperature(f32),
+    Model(String),
+    Cancel,
+}
+
+pub enum SecretAction {
+    Redact,
+    Continue,
+    Abort,
+}
+
+pub enum SplitAction {
+    Commit,
+    Move,
+    Cancel,
+}
+
+pub enum Staging {
+    All,
+    Files,
+    Hunks,
+    Staged,
+}
+
+pub enum HunkAction {
+    Stage,
+    Skip,
+    StageRest,
+    SkipRest,
+    Done,
+}